@@ -15,6 +15,8 @@ use rp_pico::entry;
 use rp_pico::hal;
 use rp_pico::hal::pac;
 use rp_pico::hal::Clock;
+use tca9555::compat::Eh02I2c;
+use tca9555::keypad::{KeyEvent, Keypad};
 use tca9555::Tca9555;
 
 #[entry]
@@ -74,13 +76,20 @@ fn main() -> ! {
         clocks.peripheral_clock,
     );
 
-    let mut tca = Tca9555::new(i2c, tca9555::DeviceAddr::default());
+    let mut tca =
+        Tca9555::new(Eh02I2c::new(i2c), tca9555::DeviceAddr::default());
+    let mut keypad = Keypad::pim551();
+    keypad.init(&mut tca).unwrap();
 
     loop {
-        let port0 = tca.read_all().unwrap();
-        info!("port 0 is: {:04x}", port0);
-        led.toggle().unwrap();
-
-        delay.delay_ms(500);
+        for event in keypad.scan(&mut tca).unwrap() {
+            match event {
+                KeyEvent::Down(key) => info!("key {} pressed", key),
+                KeyEvent::Up(key) => info!("key {} released", key),
+            }
+            led.toggle().unwrap();
+        }
+
+        delay.delay_ms(5);
     }
 }
@@ -0,0 +1,395 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! "Agile I/O" registers of the PCAL9555A and PCAL6416A.
+//!
+//! These chips share the TCA9555 registers at 0x00-0x07, and add registers
+//! at 0x40 and above for the output drive strength, input latching,
+//! pull-up and pull-down resistors, interrupt masking and status, and
+//! open-drain outputs. The methods here are only available for chips
+//! implementing [`crate::chip::AgileIo`].
+//!
+//! These registers are not held in the register cache. Each can be read
+//! back, and methods which change some pins of a register read it from
+//! the chip first. The blocking and async drivers have the same methods.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::agile::Pull;
+//! use tca9555::{DeviceAddr, Error, Pcal9555a};
+//! fn buttons<I2C: I2c>(i2c: I2C) -> Result<(), Error<I2C::Error>> {
+//!     let mut pcal = Pcal9555a::for_chip(i2c, DeviceAddr::default())?;
+//!     // Buttons to ground on pins 0-3, with only those raising INT
+//!     pcal.set_pulls(0x000f, Pull::Up)?;
+//!     pcal.set_interrupt_mask_all(!0x000f)?;
+//!     let pending = pcal.read_interrupt_status()?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::chip::AgileIo;
+use crate::command::*;
+use crate::registers::{pin_mask, RegisterWrite};
+use crate::{Error, Tca9555};
+use embedded_hal::i2c::I2c;
+
+/// Output drive strength, as a fraction of the full drive current
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum DriveStrength {
+    /// 0.25x drive
+    Quarter = 0b00,
+    /// 0.5x drive
+    Half = 0b01,
+    /// 0.75x drive
+    ThreeQuarters = 0b10,
+    /// Full drive, the power-on setting
+    Full = 0b11,
+}
+
+impl DriveStrength {
+    /// The drive strength held in the low two bits of `bits`
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::Quarter,
+            0b01 => Self::Half,
+            0b10 => Self::ThreeQuarters,
+            _ => Self::Full,
+        }
+    }
+}
+
+/// Internal pull resistor of an input
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Pull {
+    /// No pull resistor, the power-on setting
+    Floating,
+    /// Pull-up resistor
+    Up,
+    /// Pull-down resistor
+    Down,
+}
+
+impl Pull {
+    /// Apply the pull to the pins selected by `mask`, returning the new
+    /// values of the pull enable and pull select registers
+    fn apply(self, mask: u16, enable: u16, select: u16) -> (u16, u16) {
+        match self {
+            Self::Floating => (enable & !mask, select),
+            Self::Up => (enable | mask, select | mask),
+            Self::Down => (enable | mask, select & !mask),
+        }
+    }
+
+    /// The pull of the pin selected by `mask`, given the values of the
+    /// pull enable and pull select registers
+    fn of(mask: u16, enable: u16, select: u16) -> Self {
+        if enable & mask == 0 {
+            Self::Floating
+        } else if select & mask != 0 {
+            Self::Up
+        } else {
+            Self::Down
+        }
+    }
+}
+
+/// New value of the drive strength register holding `pin`, given its
+/// current value
+fn drive_strength(pin: u8, strength: DriveStrength, current: u8) -> u8 {
+    let shift = (pin % 4) * 2;
+    (current & !(0b11 << shift)) | ((strength as u8) << shift)
+}
+
+/// Define the Agile I/O methods of a driver, in the same way as the
+/// register access methods shared by both drivers
+macro_rules! agile_methods {
+    ($driver:ident, $io:ident $(, $async:tt)?) => {
+        impl<I2C, E, C: AgileIo> $driver<I2C, C>
+        where
+            I2C: I2c<Error = E>,
+        {
+            /// Set the output drive strength of a single pin
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15
+            pub $($async)? fn set_drive_strength(
+                &mut self,
+                pin: u8,
+                strength: DriveStrength,
+            ) -> Result<(), Error<E>> {
+                pin_mask(pin, C::PINS)?;
+                let register = OUTPUT_DRIVE_STRENGTH_0 + pin / 4;
+                let current = $io!(self.read_register(register))?;
+                let value = drive_strength(pin, strength, current);
+                $io!(self.write_register(RegisterWrite::single(
+                    register, value
+                )))
+            }
+
+            /// Read the output drive strength of a single pin
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15
+            pub $($async)? fn read_drive_strength(
+                &mut self,
+                pin: u8,
+            ) -> Result<DriveStrength, Error<E>> {
+                pin_mask(pin, C::PINS)?;
+                let register = OUTPUT_DRIVE_STRENGTH_0 + pin / 4;
+                let value = $io!(self.read_register(register))?;
+                Ok(DriveStrength::from_bits(value >> ((pin % 4) * 2)))
+            }
+
+            /// Set both input latch registers. Bits set to 1 hold a change
+            /// on that input, and the interrupt it raised, until the input
+            /// port is read.
+            pub $($async)? fn set_input_latch_all(
+                &mut self,
+                mask: u16,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_register(RegisterWrite::pair(
+                    INPUT_LATCH_PORT_0,
+                    mask
+                )))
+            }
+
+            /// Read both input latch registers
+            pub $($async)? fn read_input_latch_all(
+                &mut self,
+            ) -> Result<u16, Error<E>> {
+                $io!(self.read_register_pair(INPUT_LATCH_PORT_0))
+            }
+
+            /// Set the pull resistors of the pins selected by `mask`,
+            /// leaving the others unchanged. The pull direction is selected
+            /// before the resistors are enabled.
+            pub $($async)? fn set_pulls(
+                &mut self,
+                mask: u16,
+                pull: Pull,
+            ) -> Result<(), Error<E>> {
+                let enable = $io!(self.read_pull_enable_all())?;
+                let select = $io!(self.read_pull_select_all())?;
+                let (enable, select) = pull.apply(mask, enable, select);
+                if pull != Pull::Floating {
+                    $io!(self.write_register(RegisterWrite::pair(
+                        PULL_SELECT_PORT_0,
+                        select,
+                    )))?;
+                }
+                $io!(self.write_register(RegisterWrite::pair(
+                    PULL_ENABLE_PORT_0,
+                    enable
+                )))
+            }
+
+            /// Set the pull resistor of a single pin
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15
+            pub $($async)? fn set_pull(
+                &mut self,
+                pin: u8,
+                pull: Pull,
+            ) -> Result<(), Error<E>> {
+                $io!(self.set_pulls(pin_mask(pin, C::PINS)?, pull))
+            }
+
+            /// Read the pull resistor of a single pin
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15
+            pub $($async)? fn read_pull(
+                &mut self,
+                pin: u8,
+            ) -> Result<Pull, Error<E>> {
+                let mask = pin_mask(pin, C::PINS)?;
+                let enable = $io!(self.read_pull_enable_all())?;
+                let select = $io!(self.read_pull_select_all())?;
+                Ok(Pull::of(mask, enable, select))
+            }
+
+            /// Read both pull enable registers, where bits set to 1 have a
+            /// pull resistor enabled
+            pub $($async)? fn read_pull_enable_all(
+                &mut self,
+            ) -> Result<u16, Error<E>> {
+                $io!(self.read_register_pair(PULL_ENABLE_PORT_0))
+            }
+
+            /// Read both pull select registers, where bits set to 1 select
+            /// a pull-up and bits set to 0 a pull-down
+            pub $($async)? fn read_pull_select_all(
+                &mut self,
+            ) -> Result<u16, Error<E>> {
+                $io!(self.read_register_pair(PULL_SELECT_PORT_0))
+            }
+
+            /// Set both interrupt mask registers. Bits set to 1 stop that
+            /// input from asserting INT; all inputs are masked at power-on.
+            pub $($async)? fn set_interrupt_mask_all(
+                &mut self,
+                mask: u16,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_register(RegisterWrite::pair(
+                    INTERRUPT_MASK_PORT_0,
+                    mask
+                )))
+            }
+
+            /// Read both interrupt mask registers
+            pub $($async)? fn read_interrupt_mask_all(
+                &mut self,
+            ) -> Result<u16, Error<E>> {
+                $io!(self.read_register_pair(INTERRUPT_MASK_PORT_0))
+            }
+
+            /// Read both interrupt status registers, where bits set to 1
+            /// are the inputs which caused the current interrupt. The
+            /// status is cleared by reading the input ports, not by this
+            /// read.
+            pub $($async)? fn read_interrupt_status(
+                &mut self,
+            ) -> Result<u16, Error<E>> {
+                $io!(self.read_register_pair(INTERRUPT_STATUS_PORT_0))
+            }
+
+            /// Select open-drain outputs for port 0 and port 1 separately,
+            /// rather than the power-on push-pull outputs
+            pub $($async)? fn set_open_drain(
+                &mut self,
+                port0: bool,
+                port1: bool,
+            ) -> Result<(), Error<E>> {
+                let value = u8::from(port0) | (u8::from(port1) << 1);
+                $io!(self.write_register(RegisterWrite::single(
+                    OUTPUT_PORT_CONFIGURATION,
+                    value,
+                )))
+            }
+
+            /// Read whether port 0 and port 1 have open-drain outputs
+            pub $($async)? fn read_open_drain(
+                &mut self,
+            ) -> Result<(bool, bool), Error<E>> {
+                let value =
+                    $io!(self.read_register(OUTPUT_PORT_CONFIGURATION))?;
+                Ok((value & 1 != 0, value & 2 != 0))
+            }
+        }
+    };
+}
+
+agile_methods!(Tca9555, blocking);
+
+#[cfg(feature = "async")]
+mod asynch {
+    use super::*;
+    use crate::Tca9555Async;
+    use embedded_hal_async::i2c::I2c;
+
+    agile_methods!(Tca9555Async, awaited, async);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DeviceAddr, Pcal9555a};
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    #[test]
+    fn pulls_and_interrupt_mask() {
+        let expectations = [
+            Transaction::write_read(
+                0x20,
+                vec![PULL_ENABLE_PORT_0],
+                vec![0x00, 0x01],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![PULL_SELECT_PORT_0],
+                vec![0xff, 0xff],
+            ),
+            Transaction::write(0x20, vec![PULL_SELECT_PORT_0, 0xff, 0x7f]),
+            Transaction::write(0x20, vec![PULL_ENABLE_PORT_0, 0x00, 0x81]),
+            Transaction::write(0x20, vec![INTERRUPT_MASK_PORT_0, 0xf0, 0xff]),
+            Transaction::write_read(
+                0x20,
+                vec![INTERRUPT_STATUS_PORT_0],
+                vec![0x04, 0x00],
+            ),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut pcal =
+            Pcal9555a::for_chip(i2c.clone(), DeviceAddr::default()).unwrap();
+        pcal.set_pull(15, Pull::Down).unwrap();
+        pcal.set_interrupt_mask_all(0xfff0).unwrap();
+        assert_eq!(pcal.read_interrupt_status(), Ok(0x0004));
+        assert_eq!(pcal.set_pull(16, Pull::Up), Err(Error::InvalidPin(16)));
+        i2c.done();
+    }
+
+    #[test]
+    fn drive_strength_and_open_drain() {
+        let expectations = [
+            Transaction::write_read(0x20, vec![0x43], vec![0xff]),
+            Transaction::write(0x20, vec![0x43, 0xdf]),
+            Transaction::write(0x20, vec![OUTPUT_PORT_CONFIGURATION, 0x02]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut pcal =
+            Pcal9555a::for_chip(i2c.clone(), DeviceAddr::default()).unwrap();
+        pcal.set_drive_strength(14, DriveStrength::Half).unwrap();
+        pcal.set_open_drain(false, true).unwrap();
+        i2c.done();
+    }
+
+    #[test]
+    fn read_back_registers() {
+        let expectations = [
+            Transaction::write_read(0x20, vec![0x43], vec![0xdf]),
+            Transaction::write_read(
+                0x20,
+                vec![INPUT_LATCH_PORT_0],
+                vec![0x0f, 0x00],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![PULL_ENABLE_PORT_0],
+                vec![0x00, 0x81],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![PULL_SELECT_PORT_0],
+                vec![0xff, 0x7f],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![INTERRUPT_MASK_PORT_0],
+                vec![0xf0, 0xff],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![OUTPUT_PORT_CONFIGURATION],
+                vec![0x02],
+            ),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut pcal =
+            Pcal9555a::for_chip(i2c.clone(), DeviceAddr::default()).unwrap();
+        assert_eq!(pcal.read_drive_strength(14), Ok(DriveStrength::Half));
+        assert_eq!(pcal.read_input_latch_all(), Ok(0x000f));
+        assert_eq!(pcal.read_pull(15), Ok(Pull::Down));
+        assert_eq!(pcal.read_interrupt_mask_all(), Ok(0xfff0));
+        assert_eq!(pcal.read_open_drain(), Ok((false, true)));
+        assert_eq!(pcal.read_pull(16), Err(Error::InvalidPin(16)));
+        i2c.done();
+    }
+}
@@ -0,0 +1,154 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Async driver built on the embedded-hal-async [`I2c`] trait.
+//!
+//! [`Tca9555Async`] mirrors the blocking [`Tca9555`](crate::Tca9555)
+//! driver. Both drivers' register access methods are generated from one
+//! definition, so the two have the same methods and behave identically on
+//! the bus.
+//!
+//! ```no_run
+//! use embedded_hal_async::i2c::I2c;
+//! use tca9555::{DeviceAddr, Error, Tca9555Async};
+//! async fn toggle<E>(i2c: impl I2c<Error = E>) -> Result<(), Error<E>> {
+//!     let mut tca = Tca9555Async::new(i2c, DeviceAddr::default());
+//!     tca.set_direction_all(0xff00).await?;
+//!     let inputs = tca.read_all().await?;
+//!     tca.write_all(inputs >> 8).await
+//! }
+//! ```
+
+use crate::chip::{self, Chip, TwoPorts};
+use crate::command::*;
+use crate::registers::{pin_mask, replace_port, RegisterWrite};
+use crate::{AddrError, Config, DeviceAddr, Error, Registers};
+use core::marker::PhantomData;
+use embedded_hal_async::i2c::I2c;
+
+/// Async TCA9555 device, or another chip in the family selected by the
+/// [`chip`] marker `C`
+pub struct Tca9555Async<I2C, C = chip::Tca9555> {
+    address: DeviceAddr,
+    i2c: I2C,
+    pub(crate) registers: Registers,
+    inputs: u16,
+    pub(crate) safe_state: Config,
+    chip: PhantomData<C>,
+}
+
+impl<I2C> Tca9555Async<I2C> {
+    /// Create a TCA9555 device with the given address. The register cache
+    /// is assumed to hold the power-on defaults; use
+    /// [`refresh_registers`](Self::refresh_registers) if the chip may
+    /// already have been configured.
+    pub fn new(i2c: I2C, address: DeviceAddr) -> Self {
+        Self::with_address(i2c, address)
+    }
+}
+
+impl<I2C, C: Chip> Tca9555Async<I2C, C> {
+    /// Create a driver for a chip in the family other than the TCA9555,
+    /// such as `Pca9554::for_chip(i2c, address)`. The register cache is
+    /// assumed to hold the power-on defaults.
+    ///
+    /// # Errors
+    /// Returns [`AddrError::UnsupportedPins`] if `address` uses an address
+    /// pin which the chip does not have, such as A2 on a TCA9539
+    pub fn for_chip(i2c: I2C, address: DeviceAddr) -> Result<Self, AddrError> {
+        chip::check_address::<C>(address)?;
+        Ok(Self::with_address(i2c, address))
+    }
+
+    /// Create a driver for an address which is known to suit the chip
+    fn with_address(i2c: I2C, address: DeviceAddr) -> Self {
+        Self {
+            i2c,
+            address,
+            registers: Registers::POWER_ON,
+            inputs: 0xffff,
+            safe_state: Config::new(),
+            chip: PhantomData,
+        }
+    }
+
+    /// Get the cached copy of the output, polarity inversion and
+    /// configuration registers
+    pub fn registers(&self) -> Registers {
+        self.registers
+    }
+
+    /// Get the input levels from the most recent read of the input ports.
+    /// All inputs are assumed to be high before the first read.
+    pub fn last_inputs(&self) -> u16 {
+        self.inputs
+    }
+
+    /// Return the register cache and last inputs to the power-on defaults,
+    /// after the chip has been reset
+    pub(crate) fn reset_cache(&mut self) {
+        self.registers = Registers::POWER_ON;
+        self.inputs = 0xffff;
+    }
+
+    /// Destroy the driver and return the I2C bus. The chip keeps its
+    /// current register contents.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+driver_methods!(Tca9555Async, awaited, async);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    use pollster::block_on;
+
+    #[test]
+    fn matches_blocking_transactions() {
+        let expectations = [
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0x00, 0xff]),
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0x00, 0x5a]),
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0x5a, 0x00]),
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0x58]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555Async::new(i2c.clone(), DeviceAddr::default());
+        block_on(async {
+            tca.set_direction_all(0xff00).await.unwrap();
+            let inputs = tca.read_all().await.unwrap();
+            tca.write_all(inputs >> 8).await.unwrap();
+            tca.set_pin_low(1).await.unwrap();
+        });
+        assert_eq!(tca.registers().output, 0x0058);
+        i2c.done();
+    }
+
+    #[test]
+    fn read_back_registers() {
+        let expectations = [
+            Transaction::write_read(0x20, vec![WRITE_PORT_1], vec![0x0f]),
+            Transaction::write_read(
+                0x20,
+                vec![POLARITY_INVERT_PORT_0],
+                vec![0x01, 0x80],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![CONFIGURATION_PORT_0],
+                vec![0xf0],
+            ),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555Async::new(i2c.clone(), DeviceAddr::default());
+        block_on(async {
+            assert_eq!(tca.read_output_port_1().await, Ok(0x0f));
+            assert_eq!(tca.read_polarity_invert_all().await, Ok(0x8001));
+            assert_eq!(tca.read_direction_0().await, Ok(0xf0));
+        });
+        i2c.done();
+    }
+}
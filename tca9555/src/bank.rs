@@ -0,0 +1,367 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Several chips sharing one I2C bus, addressed as a single bank of pins.
+//!
+//! Up to eight TCA9555s can share a bus using the three address pins.
+//! [`Tca9555Bank`] owns the bus and keeps a register cache for each chip,
+//! numbering the pins globally so that chip `n` provides pins `16 * n` to
+//! `16 * n + 15`. A bank of another [`chip`] in the family holds one chip
+//! per address, with chip `n` providing pins `C::PINS * n` onwards, so 8
+//! pins per chip for the single-port chips. Operations on every chip
+//! return one result per chip, so a fault on one chip does not prevent the
+//! others from being used.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::{bank::Tca9555Bank, DeviceAddr, Error};
+//! fn blink<I2C: I2c>(i2c: I2C) -> Result<(), Error<I2C::Error>> {
+//!     let mut bank = Tca9555Bank::new(
+//!         i2c,
+//!         [
+//!             DeviceAddr::Default,
+//!             DeviceAddr::Alternative(true, false, false),
+//!         ],
+//!     )?;
+//!     // Pin 17 is pin 1 of the second chip
+//!     bank.set_pin_as_output(17)?;
+//!     bank.toggle_pin(17)?;
+//!     for (chip, inputs) in bank.read_all().into_iter().enumerate() {
+//!         let inputs = inputs?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::chip::{self, Chip};
+use crate::{AddrError, Config, DeviceAddr, Error, Registers, Tca9555};
+use core::marker::PhantomData;
+use embedded_hal::i2c::I2c;
+
+/// Per-chip state kept by the bank while the bus is not lent to a driver
+#[derive(Copy, Clone, Debug)]
+struct ChipState {
+    address: DeviceAddr,
+    registers: Registers,
+    inputs: u16,
+    safe_state: Config,
+}
+
+/// Bank of up to eight TCA9555s on one I2C bus, with pins numbered
+/// globally from 0 to `C::PINS * N - 1`
+pub struct Tca9555Bank<I2C, const N: usize, C = chip::Tca9555> {
+    i2c: I2C,
+    chips: [ChipState; N],
+    chip: PhantomData<C>,
+}
+
+impl<I2C, const N: usize> Tca9555Bank<I2C, N> {
+    /// Create a bank with the chips at the given addresses. Chip `n` in
+    /// the array provides global pins `16 * n` onwards. As with
+    /// [`Tca9555::new`] the register caches are assumed to hold the
+    /// power-on defaults.
+    ///
+    /// # Errors
+    /// Returns [`AddrError::Duplicate`] if an address appears more than
+    /// once, since two register caches for one chip would each undo the
+    /// other's changes
+    pub fn new(
+        i2c: I2C,
+        addresses: [DeviceAddr; N],
+    ) -> Result<Self, AddrError> {
+        Self::for_chip(i2c, addresses)
+    }
+}
+
+impl<I2C, const N: usize, C: Chip> Tca9555Bank<I2C, N, C> {
+    const VALID_SIZE: () = assert!(
+        N >= 1 && N <= 1 << C::ADDRESS_PINS,
+        "a bank holds between 1 chip and one chip per address"
+    );
+
+    /// Create a bank of another chip in the family, as
+    /// [`new`](Tca9555Bank::new) does for the TCA9555
+    ///
+    /// # Errors
+    /// Returns [`AddrError::UnsupportedPins`] if an address uses an address
+    /// pin which the chip does not have, or [`AddrError::Duplicate`] if an
+    /// address appears more than once
+    pub fn for_chip(
+        i2c: I2C,
+        addresses: [DeviceAddr; N],
+    ) -> Result<Self, AddrError> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_SIZE;
+        for (n, &address) in addresses.iter().enumerate() {
+            chip::check_address::<C>(address)?;
+            if addresses[..n].contains(&address) {
+                return Err(AddrError::Duplicate(address));
+            }
+        }
+        Ok(Self {
+            i2c,
+            chips: addresses.map(|address| ChipState {
+                address,
+                registers: Registers::POWER_ON,
+                inputs: 0xffff,
+                safe_state: Config::new(),
+            }),
+            chip: PhantomData,
+        })
+    }
+
+    /// Addresses of the chips, in bank order
+    pub fn addresses(&self) -> [DeviceAddr; N] {
+        self.chips.map(|chip| chip.address)
+    }
+
+    /// Get the cached registers of each chip
+    pub fn registers(&self) -> [Registers; N] {
+        self.chips.map(|chip| chip.registers)
+    }
+
+    /// Get the input levels of each chip from the most recent read of its
+    /// input ports
+    pub fn last_inputs(&self) -> [u16; N] {
+        self.chips.map(|chip| chip.inputs)
+    }
+
+    /// Destroy the bank and return the I2C bus. The chips keep their
+    /// current register contents.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C, E, const N: usize, C: Chip> Tca9555Bank<I2C, N, C>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Run `f` with a driver for a single chip which borrows the bank's
+    /// bus. Any changes to the chip's register cache are kept by the bank.
+    ///
+    /// # Panics
+    /// If `chip` is not less than `N`
+    pub fn with_chip<R>(
+        &mut self,
+        chip: usize,
+        f: impl FnOnce(&mut Tca9555<&mut I2C, C>) -> R,
+    ) -> R {
+        let state = &mut self.chips[chip];
+        let mut tca = Tca9555 {
+            address: state.address,
+            i2c: &mut self.i2c,
+            registers: state.registers,
+            inputs: state.inputs,
+            safe_state: state.safe_state,
+            chip: PhantomData,
+        };
+        let result = f(&mut tca);
+        state.registers = tca.registers;
+        state.inputs = tca.inputs;
+        state.safe_state = tca.safe_state;
+        result
+    }
+
+    /// Run `f` on the chip which provides the global pin `pin`, passing the
+    /// index of the pin on that chip. Errors refer to the global index.
+    fn with_pin(
+        &mut self,
+        pin: u8,
+        f: impl FnOnce(&mut Tca9555<&mut I2C, C>, u8) -> Result<(), Error<E>>,
+    ) -> Result<(), Error<E>> {
+        let chip = usize::from(pin / C::PINS);
+        if chip >= N {
+            return Err(Error::InvalidPin(pin));
+        }
+        self.with_chip(chip, |tca| f(tca, pin % C::PINS))
+            .map_err(|error| match error {
+                Error::InvalidPin(_) => Error::InvalidPin(pin),
+                Error::PinIsInput(_) => Error::PinIsInput(pin),
+                error => error,
+            })
+    }
+
+    /// Run `f` on every chip in turn, collecting the results
+    fn each_chip<T, F>(&mut self, mut f: F) -> [Result<T, Error<E>>; N]
+    where
+        F: FnMut(&mut Tca9555<&mut I2C, C>, usize) -> Result<T, Error<E>>,
+    {
+        core::array::from_fn(|chip| self.with_chip(chip, |tca| f(tca, chip)))
+    }
+
+    /// Read both input ports of every chip, one transaction per chip
+    pub fn read_all(&mut self) -> [Result<u16, Error<E>>; N] {
+        self.each_chip(|tca, _| tca.read_all())
+    }
+
+    /// Write both output ports of every chip, one transaction per chip,
+    /// where `values[n]` is written to chip `n`
+    pub fn write_all(&mut self, values: [u16; N]) -> [Result<(), Error<E>>; N] {
+        self.each_chip(|tca, chip| tca.write_all(values[chip]))
+    }
+
+    /// Configure the pin directions of every chip, where `dir_masks[n]` is
+    /// written to chip `n`
+    pub fn set_direction_all(
+        &mut self,
+        dir_masks: [u16; N],
+    ) -> [Result<(), Error<E>>; N] {
+        self.each_chip(|tca, chip| tca.set_direction_all(dir_masks[chip]))
+    }
+
+    /// Read the level of a single pin, using the global pin index
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidPin`] if `pin` is not less than `C::PINS * N`
+    pub fn is_pin_high(&mut self, pin: u8) -> Result<bool, Error<E>> {
+        let mut high = false;
+        self.with_pin(pin, |tca, pin| {
+            let port = tca.read_port(pin / 8)?;
+            high = port & (1 << (pin % 8)) != 0;
+            Ok(())
+        })?;
+        Ok(high)
+    }
+
+    /// Drive a single output pin high, using the global pin index
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidPin`] if `pin` is not less than `C::PINS * N`,
+    /// or [`Error::PinIsInput`] if the pin is configured as an input
+    pub fn set_pin_high(&mut self, pin: u8) -> Result<(), Error<E>> {
+        self.with_pin(pin, |tca, pin| tca.set_pin_high(pin))
+    }
+
+    /// Drive a single output pin low, using the global pin index
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidPin`] if `pin` is not less than `C::PINS * N`,
+    /// or [`Error::PinIsInput`] if the pin is configured as an input
+    pub fn set_pin_low(&mut self, pin: u8) -> Result<(), Error<E>> {
+        self.with_pin(pin, |tca, pin| tca.set_pin_low(pin))
+    }
+
+    /// Toggle a single output pin, using the global pin index
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidPin`] if `pin` is not less than `C::PINS * N`,
+    /// or [`Error::PinIsInput`] if the pin is configured as an input
+    pub fn toggle_pin(&mut self, pin: u8) -> Result<(), Error<E>> {
+        self.with_pin(pin, |tca, pin| tca.toggle_pin(pin))
+    }
+
+    /// Configure a single pin as an output, using the global pin index
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidPin`] if `pin` is not less than `C::PINS * N`
+    pub fn set_pin_as_output(&mut self, pin: u8) -> Result<(), Error<E>> {
+        self.with_pin(pin, |tca, pin| tca.set_pin_as_output(pin))
+    }
+
+    /// Configure a single pin as an input, using the global pin index
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidPin`] if `pin` is not less than `C::PINS * N`
+    pub fn set_pin_as_input(&mut self, pin: u8) -> Result<(), Error<E>> {
+        self.with_pin(pin, |tca, pin| tca.set_pin_as_input(pin))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::*;
+    use embedded_hal::i2c::ErrorKind;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    const ADDRESSES: [DeviceAddr; 3] = [
+        DeviceAddr::Default,
+        DeviceAddr::Alternative(true, false, false),
+        DeviceAddr::Alternative(false, true, true),
+    ];
+
+    #[test]
+    fn batched_with_per_chip_errors() {
+        let error = ErrorKind::Other;
+        let expectations = [
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0x01, 0x00]),
+            Transaction::write_read(0x21, vec![READ_PORT_0], vec![0, 0])
+                .with_error(error),
+            Transaction::write_read(0x26, vec![READ_PORT_0], vec![0x00, 0x80]),
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0x34, 0x12]),
+            Transaction::write(0x21, vec![WRITE_PORT_0, 0x00, 0x00]),
+            Transaction::write(0x26, vec![WRITE_PORT_0, 0xff, 0xff]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut bank = Tca9555Bank::new(i2c.clone(), ADDRESSES).unwrap();
+        assert_eq!(
+            bank.read_all(),
+            [Ok(0x0001), Err(Error::Bus(error)), Ok(0x8000)]
+        );
+        assert_eq!(bank.last_inputs(), [0x0001, 0xffff, 0x8000]);
+        assert!(bank
+            .write_all([0x1234, 0x0000, 0xffff])
+            .iter()
+            .all(Result::is_ok));
+        assert_eq!(bank.registers()[0].output, 0x1234);
+        i2c.done();
+    }
+
+    #[test]
+    fn global_pin_indices() {
+        let expectations = [
+            Transaction::write(0x26, vec![CONFIGURATION_PORT_1, 0xfb]),
+            Transaction::write(0x26, vec![WRITE_PORT_1, 0xfb]),
+            Transaction::write_read(0x21, vec![READ_PORT_1], vec![0x80]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut bank = Tca9555Bank::new(i2c.clone(), ADDRESSES).unwrap();
+        bank.set_pin_as_output(42).unwrap();
+        bank.set_pin_low(42).unwrap();
+        assert!(bank.is_pin_high(31).unwrap());
+        assert_eq!(bank.set_pin_high(17), Err(Error::PinIsInput(17)));
+        assert_eq!(bank.set_pin_high(48), Err(Error::InvalidPin(48)));
+        i2c.done();
+    }
+
+    #[test]
+    fn duplicate_addresses_rejected() {
+        let mut i2c = Mock::new(&[]);
+        let addresses = [
+            DeviceAddr::Default,
+            DeviceAddr::Alternative(true, false, false),
+            DeviceAddr::Alternative(false, false, false),
+        ];
+        assert!(matches!(
+            Tca9555Bank::new(i2c.clone(), addresses),
+            Err(AddrError::Duplicate(DeviceAddr::Alternative(
+                false, false, false
+            )))
+        ));
+        i2c.done();
+    }
+
+    #[cfg(feature = "sim")]
+    #[test]
+    fn chips_on_simulated_bus() {
+        use crate::sim::{SimI2c, Tca9555Sim};
+        let chips = ADDRESSES.map(Tca9555Sim::new);
+        let mut bank =
+            Tca9555Bank::new(SimI2c::new(&chips), ADDRESSES).unwrap();
+        let results = bank.set_direction_all([0xffff, 0x00ff, 0xffff]);
+        assert!(results.iter().all(Result::is_ok));
+        let results = bank.write_all([0, 0xa500, 0]);
+        assert!(results.iter().all(Result::is_ok));
+        chips[2].set_input(0, false);
+        assert_eq!(chips[1].pin_levels(), 0xa5ff);
+        assert_eq!(
+            bank.read_all().map(Result::unwrap),
+            [0xffff, 0xa5ff, 0xfffe]
+        );
+        bank.with_chip(0, |tca| tca.set_pin_as_output(3)).unwrap();
+        bank.set_pin_low(3).unwrap();
+        assert_eq!(chips[0].pin_levels(), 0xfff7);
+    }
+}
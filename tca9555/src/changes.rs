@@ -0,0 +1,203 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Edge detection between consecutive input samples.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::changes::{Edge, InputTracker};
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn watch<I2C: I2c>(i2c: I2C) -> Result<(), Error<I2C::Error>> {
+//!     let mut tca = Tca9555::new(i2c, DeviceAddr::default());
+//!     let mut tracker = InputTracker::new(tca.read_all()?);
+//!     loop {
+//!         for event in tracker.sample(&mut tca)?.edges() {
+//!             if event.edge == Edge::Falling {
+//!                 // pin `event.pin` has been pulled low
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::chip::Chip;
+use crate::{Error, Tca9555};
+use embedded_hal::i2c::I2c;
+
+/// The difference between two input samples. Bit `n` of each mask
+/// corresponds to pin `n`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct Changes {
+    /// Pins which went from low to high
+    pub rising: u16,
+    /// Pins which went from high to low
+    pub falling: u16,
+    /// Pins which changed in either direction
+    pub changed: u16,
+}
+
+impl Changes {
+    /// Compare two samples of the inputs
+    pub fn between(previous: u16, current: u16) -> Self {
+        let changed = previous ^ current;
+        Self {
+            rising: changed & current,
+            falling: changed & previous,
+            changed,
+        }
+    }
+
+    /// Returns `true` if no pins changed
+    pub fn is_empty(&self) -> bool {
+        self.changed == 0
+    }
+
+    /// Iterate over the individual pin edges, in order of pin index
+    pub fn edges(&self) -> Edges {
+        Edges {
+            remaining: self.changed,
+            rising: self.rising,
+        }
+    }
+}
+
+/// Direction of a change on an input
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Edge {
+    /// The input went from low to high
+    Rising,
+    /// The input went from high to low
+    Falling,
+}
+
+/// A change on a single pin
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct PinEdge {
+    /// Index of the pin, where pins 0-7 are port 0 and 8-15 are port 1
+    pub pin: u8,
+    /// Direction of the change
+    pub edge: Edge,
+}
+
+/// Iterator over the edges in a [`Changes`], as returned by
+/// [`Changes::edges`]
+pub struct Edges {
+    remaining: u16,
+    rising: u16,
+}
+
+impl Iterator for Edges {
+    type Item = PinEdge;
+
+    fn next(&mut self) -> Option<PinEdge> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let pin = self.remaining.trailing_zeros() as u8;
+        let mask = 1 << pin;
+        self.remaining &= !mask;
+        let edge = if self.rising & mask != 0 {
+            Edge::Rising
+        } else {
+            Edge::Falling
+        };
+        Some(PinEdge { pin, edge })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.remaining.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Edges {}
+
+/// Keeps the previous input sample so that each new sample can be
+/// reported as a set of [`Changes`]. Several trackers can be used with the
+/// same driver, for example one per consumer.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct InputTracker {
+    last: u16,
+}
+
+impl InputTracker {
+    /// Create a tracker starting from the given sample
+    pub fn new(initial: u16) -> Self {
+        Self { last: initial }
+    }
+
+    /// The most recent sample
+    pub fn last(&self) -> u16 {
+        self.last
+    }
+
+    /// Record a new sample, returning the changes since the previous one
+    pub fn update(&mut self, sample: u16) -> Changes {
+        let changes = Changes::between(self.last, sample);
+        self.last = sample;
+        changes
+    }
+
+    /// Read the inputs from the driver and record them as a new sample
+    pub fn sample<I2C: I2c, C: Chip>(
+        &mut self,
+        tca: &mut Tca9555<I2C, C>,
+    ) -> Result<Changes, Error<I2C::Error>> {
+        Ok(self.update(tca.read_all()?))
+    }
+
+    /// Read the inputs from the async driver and record them as a new
+    /// sample
+    #[cfg(feature = "async")]
+    pub async fn sample_async<I2C, C: Chip>(
+        &mut self,
+        tca: &mut crate::Tca9555Async<I2C, C>,
+    ) -> Result<Changes, Error<I2C::Error>>
+    where
+        I2C: embedded_hal_async::i2c::I2c,
+    {
+        Ok(self.update(tca.read_all().await?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rising_and_falling() {
+        let changes = Changes::between(0b1100, 0b1010);
+        assert_eq!(changes.rising, 0b0010);
+        assert_eq!(changes.falling, 0b0100);
+        assert_eq!(changes.changed, 0b0110);
+        assert!(Changes::between(0x1234, 0x1234).is_empty());
+    }
+
+    #[test]
+    fn edges_in_pin_order() {
+        let mut tracker = InputTracker::new(0x8001);
+        assert!(tracker.update(0x8001).is_empty());
+        let changes = tracker.update(0x0003);
+        let edges = changes.edges();
+        assert_eq!(edges.len(), 2);
+        assert_eq!(
+            edges.collect::<Vec<_>>(),
+            [
+                PinEdge {
+                    pin: 1,
+                    edge: Edge::Rising
+                },
+                PinEdge {
+                    pin: 15,
+                    edge: Edge::Falling
+                },
+            ]
+        );
+        assert_eq!(tracker.last(), 0x0003);
+    }
+}
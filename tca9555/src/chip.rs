@@ -0,0 +1,273 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Markers for the chips in the TCA9555 family.
+//!
+//! The drivers take a chip marker as a type parameter, defaulting to
+//! [`Tca9555`], which describes how the chip is addressed and how many
+//! ports it has. The 16-bit chips share the TCA9555 register map. The
+//! 8-bit chips have a single port with the input, output, polarity
+//! inversion and configuration registers at 0x00-0x03; the driver keeps
+//! the same 16-bit interface for them, with pins 8-15 reported as invalid
+//! and the methods for port 1 unavailable.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::{DeviceAddr, Error, Pca9554, Tca9539};
+//! fn two_chips<I2C: I2c>(
+//!     i2c: I2C,
+//!     other: I2C,
+//! ) -> Result<(), Error<I2C::Error>> {
+//!     // A TCA9539 at 0x75, with A0 high
+//!     let address = DeviceAddr::Alternative(true, false, false);
+//!     let mut tca = Tca9539::for_chip(i2c, address)?;
+//!     tca.write_port_1(0x0f)?;
+//!     // An 8-bit PCA9554 at 0x20
+//!     let mut pca = Pca9554::for_chip(other, DeviceAddr::default())?;
+//!     pca.set_port_0_direction(0xf0)?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::command::*;
+use crate::registers::RegisterWrite;
+use crate::{AddrError, DeviceAddr};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Properties of a chip in the family
+pub trait Chip: sealed::Sealed {
+    /// Part name, used in log messages
+    const NAME: &'static str;
+    /// I2C address with every address pin low
+    const BASE_ADDRESS: u8;
+    /// Number of address pins, starting from A0
+    const ADDRESS_PINS: u8;
+    /// Number of 8-bit ports
+    const PORTS: u8;
+    /// Number of I/O pins
+    const PINS: u8 = Self::PORTS * 8;
+    /// Mask of the bits which correspond to I/O pins
+    const PIN_MASK: u16 = if Self::PORTS == 2 { 0xffff } else { 0x00ff };
+}
+
+/// Chips with two ports, which provide the methods for port 1
+pub trait TwoPorts: Chip {}
+
+/// Chips with a single port
+pub trait OnePort: Chip {}
+
+/// Chips with the "Agile I/O" registers at 0x40 and above, which provide
+/// the methods in the [`agile`](crate::agile) module
+pub trait AgileIo: TwoPorts {}
+
+/// Chips with an active-low RESET input, which provide
+/// [`hard_reset`](crate::Tca9555::hard_reset)
+///
+/// ```compile_fail
+/// # use embedded_hal::{delay::DelayNs, digital::OutputPin, i2c::I2c};
+/// # use tca9555::{reset::ResetPin, DeviceAddr, Tca9555};
+/// # fn f<I2C: I2c, P: OutputPin, D: DelayNs>(
+/// #     i2c: I2C,
+/// #     reset: &mut ResetPin<P, D>,
+/// # ) {
+/// // The TCA9555 has no RESET input
+/// Tca9555::new(i2c, DeviceAddr::default()).hard_reset(reset);
+/// # }
+/// ```
+pub trait HasReset: Chip {}
+
+/// Define a chip marker
+macro_rules! chip {
+    (
+        $(#[$attr:meta])*
+        $name:ident: $part:literal, base $base:literal, $address_pins:literal
+        address pins, $ports:literal ports $marker:ident
+    ) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+        #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+        pub struct $name;
+
+        impl sealed::Sealed for $name {}
+
+        impl Chip for $name {
+            const NAME: &'static str = $part;
+            const BASE_ADDRESS: u8 = $base;
+            const ADDRESS_PINS: u8 = $address_pins;
+            const PORTS: u8 = $ports;
+        }
+
+        impl $marker for $name {}
+    };
+}
+
+chip! {
+    /// TI TCA9555, with internal pull-ups. The TCA9535 has no pull-ups but
+    /// is otherwise identical, so it uses this marker too.
+    Tca9555: "TCA9555", base 0x20, 3 address pins, 2 ports TwoPorts
+}
+
+chip! {
+    /// NXP PCA9555, with internal pull-ups
+    Pca9555: "PCA9555", base 0x20, 3 address pins, 2 ports TwoPorts
+}
+
+chip! {
+    /// TI TCA9539, without pull-ups but with a RESET input, at 0x74-0x77
+    /// using address pins A0 and A1
+    Tca9539: "TCA9539", base 0x74, 2 address pins, 2 ports TwoPorts
+}
+
+impl HasReset for Tca9539 {}
+
+chip! {
+    /// XINLUDA XL9555, a PCA9555 clone
+    Xl9555: "XL9555", base 0x20, 3 address pins, 2 ports TwoPorts
+}
+
+chip! {
+    /// NXP PCAL9555A, a PCA9555 with Agile I/O and programmable pull-up or
+    /// pull-down resistors, which are disabled at power-on
+    Pcal9555a: "PCAL9555A", base 0x20, 3 address pins, 2 ports TwoPorts
+}
+
+impl AgileIo for Pcal9555a {}
+
+chip! {
+    /// NXP PCAL6416A, with the same registers as the PCAL9555A but a single
+    /// address pin and a RESET input
+    Pcal6416a: "PCAL6416A", base 0x20, 1 address pins, 2 ports TwoPorts
+}
+
+impl AgileIo for Pcal6416a {}
+
+impl HasReset for Pcal6416a {}
+
+chip! {
+    /// NXP PCA9554, 8 pins with internal pull-ups
+    Pca9554: "PCA9554", base 0x20, 3 address pins, 1 ports OnePort
+}
+
+chip! {
+    /// TI TCA9554, 8 pins with internal pull-ups
+    Tca9554: "TCA9554", base 0x20, 3 address pins, 1 ports OnePort
+}
+
+/// Check that `address` only uses the address pins which the chip has
+pub(crate) fn check_address<C: Chip>(
+    address: DeviceAddr,
+) -> Result<(), AddrError> {
+    if address.pins() >> C::ADDRESS_PINS == 0 {
+        Ok(())
+    } else {
+        Err(AddrError::UnsupportedPins(address))
+    }
+}
+
+/// The I2C address of a chip
+pub(crate) fn address<C: Chip>(address: DeviceAddr) -> u8 {
+    C::BASE_ADDRESS + address.pins()
+}
+
+/// Power-on value of a register of a port which the chip does not have,
+/// reported in place of reading it. Missing inputs read high.
+fn missing_port(register: u8) -> u8 {
+    if register & !1 == POLARITY_INVERT_PORT_0 {
+        0x00
+    } else {
+        0xff
+    }
+}
+
+/// Map a read of `buffer.len()` registers of the 16-bit register map,
+/// starting at `register`, onto the chip. Returns the command and number
+/// of bytes to read from the chip, if any; the registers of a missing port
+/// are filled in with their power-on values.
+pub(crate) fn map_read<C: Chip>(
+    register: u8,
+    buffer: &mut [u8],
+) -> Option<(u8, usize)> {
+    if C::PORTS == 2 {
+        return Some((register, buffer.len()));
+    }
+    for (offset, byte) in buffer.iter_mut().enumerate() {
+        *byte = missing_port(register + offset as u8);
+    }
+    (register & 1 == 0).then_some((register >> 1, 1))
+}
+
+/// Map a write to the 16-bit register map onto the chip, dropping any
+/// bytes for a missing port. Returns `None` if nothing needs to be sent.
+pub(crate) fn map_write<C: Chip>(
+    write: RegisterWrite,
+) -> Option<RegisterWrite> {
+    if C::PORTS == 2 {
+        return Some(write);
+    }
+    match *write.as_bytes() {
+        [register, port0, ..] if register & 1 == 0 => {
+            Some(RegisterWrite::single(register >> 1, port0))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Error, Pca9554, Tca9539};
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    #[test]
+    fn tca9539_address() {
+        let mut i2c = Mock::new(&[Transaction::write(0x76, vec![0x03, 0x0f])]);
+        let mut tca = Tca9539::for_chip(
+            i2c.clone(),
+            DeviceAddr::Alternative(false, true, false),
+        )
+        .unwrap();
+        tca.write_port_1(0x0f).unwrap();
+        i2c.done();
+    }
+
+    #[test]
+    fn tca9539_has_no_a2() {
+        let a2 = DeviceAddr::Alternative(false, false, true);
+        let mut i2c = Mock::new(&[]);
+        assert!(matches!(
+            Tca9539::for_chip(i2c.clone(), a2),
+            Err(AddrError::UnsupportedPins(address)) if address == a2
+        ));
+        i2c.done();
+    }
+
+    #[test]
+    fn pca9554_register_map() {
+        let expectations = [
+            Transaction::write(0x21, vec![0x01, 0x34]),
+            Transaction::write(0x21, vec![0x03, 0xf0]),
+            Transaction::write_read(0x21, vec![0x00], vec![0x5a]),
+            Transaction::write_read(0x21, vec![0x02], vec![0x00]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut pca = Pca9554::for_chip(
+            i2c.clone(),
+            DeviceAddr::Alternative(true, false, false),
+        )
+        .unwrap();
+        pca.write_all(0x1234).unwrap();
+        // Only port 0 is written
+        pca.modify_directions(0xff0f, 0x0000).unwrap();
+        pca.modify_outputs(0xff00, 0x0000).unwrap();
+        assert_eq!(pca.registers().output, 0xff34);
+        assert_eq!(pca.registers().direction, 0xfff0);
+        assert_eq!(pca.read_all(), Ok(0xff5a));
+        assert_eq!(pca.read_polarity_invert_all(), Ok(0x0000));
+        assert_eq!(pca.set_pin_as_output(8), Err(Error::InvalidPin(8)));
+        i2c.done();
+    }
+}
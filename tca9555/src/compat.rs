@@ -0,0 +1,128 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Adapter for using the driver with an embedded-hal 0.2 I2C bus.
+//!
+//! ```no_run
+//! # use embedded_hal_02::blocking::i2c::{Write, WriteRead};
+//! use tca9555::{compat::Eh02I2c, DeviceAddr, Tca9555};
+//! # fn example<E: core::fmt::Debug>(
+//! #     i2c: impl Write<Error = E> + WriteRead<Error = E>,
+//! # ) {
+//! let mut tca = Tca9555::new(Eh02I2c::new(i2c), DeviceAddr::default());
+//! let inputs = tca.read_all();
+//! # }
+//! ```
+
+use core::fmt::Debug;
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, Operation};
+use embedded_hal_02::blocking::i2c::{Write, WriteRead};
+
+/// Wraps an I2C bus implementing the embedded-hal 0.2 `Write` and
+/// `WriteRead` traits so that it implements the embedded-hal 1.0 [`I2c`]
+/// trait.
+///
+/// Only the operation sequences which can be expressed with the 0.2 traits
+/// are supported: a write may optionally be followed by a read, and
+/// consecutive pairs are issued as separate transactions.
+pub struct Eh02I2c<I2C>(I2C);
+
+impl<I2C> Eh02I2c<I2C> {
+    /// Wrap an embedded-hal 0.2 I2C bus
+    pub fn new(i2c: I2C) -> Self {
+        Self(i2c)
+    }
+
+    /// Release the wrapped I2C bus
+    pub fn into_inner(self) -> I2C {
+        self.0
+    }
+}
+
+/// Error returned by [`Eh02I2c`]
+#[derive(Debug)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Eh02Error<E> {
+    /// Error returned by the wrapped I2C bus
+    Bus(E),
+    /// The requested operation sequence cannot be expressed with the
+    /// embedded-hal 0.2 traits
+    Unsupported,
+}
+
+impl<E: Debug> i2c::Error for Eh02Error<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl<I2C, E> ErrorType for Eh02I2c<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    type Error = Eh02Error<E>;
+}
+
+impl<I2C, E> I2c for Eh02I2c<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut operations = operations.iter_mut().peekable();
+        while let Some(operation) = operations.next() {
+            let Operation::Write(bytes) = operation else {
+                return Err(Eh02Error::Unsupported);
+            };
+            match operations.next_if(|op| matches!(op, Operation::Read(_))) {
+                Some(Operation::Read(buffer)) => {
+                    self.0.write_read(address, bytes, buffer)
+                }
+                _ => self.0.write(address, bytes),
+            }
+            .map_err(Eh02Error::Bus)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::*;
+    use crate::{DeviceAddr, Tca9555};
+    use embedded_hal_mock::eh0::i2c::{Mock, Transaction};
+
+    #[test]
+    fn driver_on_eh02_bus() {
+        let expectations = [
+            Transaction::write_read(0x21, vec![READ_PORT_0], vec![0x01, 0x80]),
+            Transaction::write(0x21, vec![WRITE_PORT_1, 0x7f]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(
+            Eh02I2c::new(i2c.clone()),
+            DeviceAddr::Alternative(true, false, false),
+        );
+        assert_eq!(tca.read_all().unwrap(), 0x8001);
+        tca.write_port_1(0x7f).unwrap();
+        i2c.done();
+    }
+
+    #[test]
+    fn read_without_write_unsupported() {
+        let mut i2c = Eh02I2c::new(Mock::new(&[]));
+        let mut buffer = [0];
+        assert!(matches!(
+            i2c.read(0x20, &mut buffer),
+            Err(Eh02Error::Unsupported)
+        ));
+        i2c.into_inner().done();
+    }
+}
@@ -0,0 +1,432 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Complete pin configuration, applied in one step.
+//!
+//! A [`Config`] describes the direction, polarity inversion and initial
+//! output level of every pin. [`Tca9555::apply`] first releases the pins
+//! which are becoming inputs, then writes the output registers before the
+//! remaining configuration, so that no pin is driven to a level it was not
+//! already at or configured to start at.
+//!
+//! Each driver also holds a safe state, which defaults to the power-on
+//! state with every pin an input. Setting it with
+//! [`Tca9555::with_safe_state`], or
+//! [`Tca9555::for_chip_with_safe_state`] for other chips in the family,
+//! applies it as soon as the driver is created, and
+//! [`Tca9555::apply_safe_state`] returns the pins to it on demand, for
+//! example when a fault is detected. Outputs enabled later with
+//! [`Tca9555::enable_outputs`] have their level written before their
+//! direction, so relays and MOSFET gates never see a glitch.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::{config::Config, DeviceAddr, Error, Tca9555};
+//! fn bring_up<I2C: I2c>(i2c: I2C) -> Result<(), Error<I2C::Error>> {
+//!     let mut tca = Tca9555::new(i2c, DeviceAddr::default());
+//!     let config = Config::new()
+//!         .output(0, false)
+//!         .output(1, true)
+//!         .inverted_input(8)
+//!         .verify(true);
+//!     tca.apply(&config)
+//! }
+//! ```
+
+use crate::chip::Chip;
+use crate::{DeviceAddr, Error, Registers, Tca9555};
+use embedded_hal::i2c::I2c;
+
+/// Direction, polarity inversion and initial output level of every pin.
+/// Pins not otherwise configured are non-inverted inputs, as at power-on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct Config {
+    registers: Registers,
+    verify: bool,
+}
+
+impl Config {
+    /// Configuration matching the power-on defaults
+    pub const fn new() -> Self {
+        Self {
+            registers: Registers::POWER_ON,
+            verify: false,
+        }
+    }
+
+    /// Configuration which writes the given register contents
+    pub const fn from_registers(registers: Registers) -> Self {
+        Self {
+            registers,
+            verify: false,
+        }
+    }
+
+    /// The register contents which will be written
+    pub fn registers(&self) -> Registers {
+        self.registers
+    }
+
+    fn set(field: &mut u16, mask: u16, value: bool) {
+        if value {
+            *field |= mask;
+        } else {
+            *field &= !mask;
+        }
+    }
+
+    fn mask(pin: u8) -> u16 {
+        assert!(pin < 16, "pin {} is out of range", pin);
+        1 << pin
+    }
+
+    /// Configure a pin as an output, starting at the given level
+    ///
+    /// # Panics
+    /// If `pin` is not in the range 0-15
+    pub fn output(mut self, pin: u8, high: bool) -> Self {
+        let mask = Self::mask(pin);
+        Self::set(&mut self.registers.output, mask, high);
+        Self::set(&mut self.registers.direction, mask, false);
+        self
+    }
+
+    /// Configure a pin as a non-inverted input
+    ///
+    /// # Panics
+    /// If `pin` is not in the range 0-15
+    pub fn input(mut self, pin: u8) -> Self {
+        let mask = Self::mask(pin);
+        Self::set(&mut self.registers.direction, mask, true);
+        Self::set(&mut self.registers.polarity_invert, mask, false);
+        self
+    }
+
+    /// Configure a pin as an input with its polarity inverted, so that it
+    /// reads as 1 when the pin is low
+    ///
+    /// # Panics
+    /// If `pin` is not in the range 0-15
+    pub fn inverted_input(mut self, pin: u8) -> Self {
+        let mask = Self::mask(pin);
+        Self::set(&mut self.registers.direction, mask, true);
+        Self::set(&mut self.registers.polarity_invert, mask, true);
+        self
+    }
+
+    /// Configure the pins selected by `mask` as outputs, starting at the
+    /// corresponding bits of `levels`
+    pub fn outputs(mut self, mask: u16, levels: u16) -> Self {
+        let registers = &mut self.registers;
+        registers.output = (registers.output & !mask) | (levels & mask);
+        registers.direction &= !mask;
+        self
+    }
+
+    /// Configure the pins selected by `mask` as inputs, with the polarity
+    /// of those selected by `inverted` inverted
+    pub fn inputs(mut self, mask: u16, inverted: u16) -> Self {
+        let registers = &mut self.registers;
+        registers.direction |= mask;
+        registers.polarity_invert =
+            (registers.polarity_invert & !mask) | (inverted & mask);
+        self
+    }
+
+    /// Read the registers back after applying the configuration, and fail
+    /// with [`Error::VerifyFailed`] if they do not match
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Define the configuration methods of a driver, in the same way as the
+/// register access methods shared by both drivers
+macro_rules! config_methods {
+    ($driver:ident, $io:ident $(, $async:tt)?) => {
+        impl<I2C, C: Chip> $driver<I2C, C> {
+            /// The configuration applied by
+            /// [`apply_safe_state`](Self::apply_safe_state)
+            pub fn safe_state(&self) -> Config {
+                self.safe_state
+            }
+
+            /// Change the safe state, without applying it
+            pub fn set_safe_state(&mut self, safe_state: Config) {
+                self.safe_state = safe_state;
+            }
+        }
+
+        impl<I2C, E> $driver<I2C>
+        where
+            I2C: I2c<Error = E>,
+        {
+            /// Create a driver and immediately put the chip into
+            /// `safe_state`, which is kept for use with
+            /// [`apply_safe_state`](Self::apply_safe_state)
+            pub $($async)? fn with_safe_state(
+                i2c: I2C,
+                address: DeviceAddr,
+                safe_state: Config,
+            ) -> Result<Self, Error<E>> {
+                $io!(Self::for_chip_with_safe_state(i2c, address, safe_state))
+            }
+        }
+
+        impl<I2C, E, C: Chip> $driver<I2C, C>
+        where
+            I2C: I2c<Error = E>,
+        {
+            /// Create a driver for another chip in the family, as
+            /// [`with_safe_state`](Self::with_safe_state) does for the
+            /// TCA9555
+            ///
+            /// # Errors
+            /// Returns [`Error::Address`] if `address` uses an address pin
+            /// which the chip does not have
+            pub $($async)? fn for_chip_with_safe_state(
+                i2c: I2C,
+                address: DeviceAddr,
+                safe_state: Config,
+            ) -> Result<Self, Error<E>> {
+                let mut tca = Self::for_chip(i2c, address)?;
+                tca.safe_state = safe_state;
+                $io!(tca.apply_safe_state())?;
+                Ok(tca)
+            }
+
+            /// Return every pin to the safe state
+            pub $($async)? fn apply_safe_state(
+                &mut self,
+            ) -> Result<(), Error<E>> {
+                let safe_state = self.safe_state;
+                $io!(self.apply(&safe_state))
+            }
+
+            /// Switch the pins selected by `mask` to outputs driving the
+            /// corresponding bits of `levels`. The output register is
+            /// written before the configuration register, so the pins never
+            /// drive the level which was previously held in the output
+            /// register.
+            pub $($async)? fn enable_outputs(
+                &mut self,
+                mask: u16,
+                levels: u16,
+            ) -> Result<(), Error<E>> {
+                $io!(self.modify_outputs(mask, levels))?;
+                $io!(self.modify_directions(mask, 0x0000))
+            }
+
+            /// Write a complete configuration to the chip. Outputs which
+            /// become inputs are switched first, so they never drive the
+            /// new output level; then the output registers, polarity
+            /// inversion and finally the remaining pin directions are
+            /// written. If the configuration requests verification the
+            /// registers are then read back.
+            pub $($async)? fn apply(
+                &mut self,
+                config: &Config,
+            ) -> Result<(), Error<E>> {
+                let registers = config.registers;
+                let to_input =
+                    registers.direction & !self.registers().direction;
+                if to_input != 0 {
+                    $io!(self.modify_directions(to_input, 0xffff))?;
+                }
+                $io!(self.write_all(registers.output))?;
+                $io!(self.set_polarity_invert_all(registers.polarity_invert))?;
+                $io!(self.set_direction_all(registers.direction))?;
+                if config.verify {
+                    $io!(self.verify_registers())?;
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+config_methods!(Tca9555, blocking);
+
+#[cfg(feature = "async")]
+mod asynch {
+    use super::*;
+    use crate::Tca9555Async;
+    use embedded_hal_async::i2c::I2c;
+
+    config_methods!(Tca9555Async, awaited, async);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::*;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    #[test]
+    fn builder() {
+        let config = Config::new()
+            .output(0, true)
+            .output(1, false)
+            .inverted_input(8)
+            .outputs(0xf000, 0x5000)
+            .inputs(0x3000, 0x2000);
+        assert_eq!(
+            config.registers(),
+            Registers {
+                output: 0x5ffd,
+                polarity_invert: 0x2100,
+                direction: 0x3ffc,
+            }
+        );
+    }
+
+    #[test]
+    fn outputs_before_directions() {
+        let expectations = [
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0xfe, 0xff]),
+            Transaction::write(0x20, vec![POLARITY_INVERT_PORT_0, 0x00, 0x80]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xfc, 0xff]),
+            Transaction::write_read(0x20, vec![WRITE_PORT_0], vec![0xfe, 0xff]),
+            Transaction::write_read(
+                0x20,
+                vec![POLARITY_INVERT_PORT_0],
+                vec![0x00, 0x80],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![CONFIGURATION_PORT_0],
+                vec![0xff, 0xff],
+            ),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        let config = Config::new()
+            .output(0, false)
+            .output(1, true)
+            .inverted_input(15)
+            .verify(true);
+        assert_eq!(
+            tca.apply(&config),
+            Err(Error::VerifyFailed {
+                register: CONFIGURATION_PORT_0,
+                expected: 0xfffc,
+                actual: 0xffff,
+            })
+        );
+        i2c.done();
+    }
+
+    #[test]
+    fn safe_state_on_init_and_demand() {
+        let expectations = [
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0xf0, 0xff]),
+            Transaction::write(0x20, vec![POLARITY_INVERT_PORT_0, 0x00, 0x00]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xf0, 0xff]),
+            Transaction::write(0x20, vec![WRITE_PORT_1, 0xfe]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_1, 0xfe]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_1, 0xff]),
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0xf0, 0xff]),
+            Transaction::write(0x20, vec![POLARITY_INVERT_PORT_0, 0x00, 0x00]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xf0, 0xff]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let safe = Config::new().outputs(0x000f, 0x0000);
+        let mut tca =
+            Tca9555::with_safe_state(i2c.clone(), DeviceAddr::default(), safe)
+                .unwrap();
+        tca.enable_outputs(0x0100, 0x0000).unwrap();
+        assert_eq!(tca.registers().direction, 0xfef0);
+        tca.apply_safe_state().unwrap();
+        assert_eq!(tca.registers(), safe.registers());
+        i2c.done();
+    }
+
+    #[test]
+    fn safe_state_for_other_chip() {
+        use crate::{AddrError, Tca9539};
+        let expectations = [
+            Transaction::write(0x75, vec![WRITE_PORT_0, 0xfe, 0xff]),
+            Transaction::write(0x75, vec![POLARITY_INVERT_PORT_0, 0x00, 0x00]),
+            Transaction::write(0x75, vec![CONFIGURATION_PORT_0, 0xfe, 0xff]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let safe = Config::new().output(0, false);
+        let a0 = DeviceAddr::Alternative(true, false, false);
+        let tca = Tca9539::for_chip_with_safe_state(i2c.clone(), a0, safe);
+        assert_eq!(tca.unwrap().safe_state(), safe);
+        let a2 = DeviceAddr::Alternative(false, false, true);
+        assert!(matches!(
+            Tca9539::for_chip_with_safe_state(i2c.clone(), a2, safe),
+            Err(Error::Address(AddrError::UnsupportedPins(_)))
+        ));
+        i2c.done();
+    }
+
+    #[cfg(feature = "sim")]
+    #[test]
+    fn outputs_released_before_levels_change() {
+        use crate::sim::{SimError, Tca9555Sim};
+        use embedded_hal::i2c::{ErrorType, Operation};
+        use std::cell::RefCell;
+
+        /// Records the pin levels and directions after every transaction
+        struct Recorder<'a> {
+            sim: &'a Tca9555Sim,
+            samples: &'a RefCell<Vec<(u16, u16)>>,
+        }
+
+        impl ErrorType for Recorder<'_> {
+            type Error = SimError;
+        }
+
+        impl I2c for Recorder<'_> {
+            fn transaction(
+                &mut self,
+                address: u8,
+                operations: &mut [Operation<'_>],
+            ) -> Result<(), SimError> {
+                self.sim.i2c().transaction(address, operations)?;
+                let sample =
+                    (self.sim.pin_levels(), self.sim.registers().direction);
+                self.samples.borrow_mut().push(sample);
+                Ok(())
+            }
+        }
+
+        let sim = Tca9555Sim::new(DeviceAddr::default());
+        let samples = RefCell::new(Vec::new());
+        let i2c = Recorder {
+            sim: &sim,
+            samples: &samples,
+        };
+        let mut tca = Tca9555::new(i2c, DeviceAddr::default());
+        // Relays held low on pins 0-3 and 8, with the rest of port 0 high
+        tca.enable_outputs(0x01ff, 0x00f0).unwrap();
+        let before = sim.pin_levels();
+        samples.borrow_mut().clear();
+        // The default safe state has every pin an input, outputs high
+        tca.apply_safe_state().unwrap();
+        let released = 0x01ff;
+        for &(levels, direction) in samples.borrow().iter() {
+            let driven = released & !direction;
+            assert_eq!(levels & driven, before & driven);
+        }
+        assert_eq!(sim.registers(), Registers::POWER_ON);
+        // Switching back to outputs never drives the stale output levels
+        samples.borrow_mut().clear();
+        tca.apply(&Config::new().outputs(0x000f, 0x0000)).unwrap();
+        for &(levels, direction) in samples.borrow().iter() {
+            let driven = 0x000f & !direction;
+            assert_eq!(levels & driven, 0);
+        }
+    }
+}
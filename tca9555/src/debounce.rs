@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Software debouncing of input samples.
+//!
+//! A [`Debouncer`] is fed periodic samples of the inputs along with a
+//! timestamp from a monotonic clock supplied by the caller, in whatever
+//! unit is convenient. Each pin only changes state once its new level has
+//! been stable for that pin's [`Threshold`], either a number of
+//! consecutive samples or a length of time.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::debounce::{Debouncer, Threshold};
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn buttons<I2C: I2c>(
+//!     i2c: I2C,
+//!     now_ms: impl Fn() -> u64,
+//! ) -> Result<(), Error<I2C::Error>> {
+//!     let mut tca = Tca9555::new(i2c, DeviceAddr::default());
+//!     // 20ms for most pins, but a slow switch on pin 15
+//!     let mut debouncer =
+//!         Debouncer::new(tca.read_all()?, Threshold::Time(20))
+//!             .with_threshold(15, Threshold::Time(50));
+//!     loop {
+//!         let changes = debouncer.sample(&mut tca, now_ms())?;
+//!         for event in changes.edges() {
+//!             // handle the debounced edge on `event.pin`
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::chip::Chip;
+use crate::{Changes, Error, Tca9555};
+use embedded_hal::i2c::I2c;
+
+/// How long a pin must hold a new level before the change is accepted
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Threshold {
+    /// The new level must be read in this many consecutive samples. A
+    /// value of 0 or 1 accepts changes immediately.
+    Samples(u16),
+    /// The new level must have been held for at least this long, in the
+    /// units of the timestamps passed to [`Debouncer::update`]
+    Time(u64),
+}
+
+/// Debounces each of the 16 inputs independently
+#[derive(Copy, Clone, Debug)]
+pub struct Debouncer {
+    thresholds: [Threshold; 16],
+    /// Accepted level of each pin
+    stable: u16,
+    /// Level of each pin in the most recent sample
+    raw: u16,
+    /// Number of consecutive samples at the current raw level
+    counts: [u16; 16],
+    /// Timestamp at which each pin changed to its current raw level
+    since: [u64; 16],
+}
+
+impl Debouncer {
+    /// Create a debouncer starting from the given sample, using the same
+    /// threshold for every pin
+    pub fn new(initial: u16, threshold: Threshold) -> Self {
+        Self {
+            thresholds: [threshold; 16],
+            stable: initial,
+            raw: initial,
+            counts: [0; 16],
+            since: [0; 16],
+        }
+    }
+
+    /// Use a different threshold for a single pin
+    ///
+    /// # Panics
+    /// If `pin` is not in the range 0-15
+    pub fn with_threshold(mut self, pin: u8, threshold: Threshold) -> Self {
+        self.set_threshold(pin, threshold);
+        self
+    }
+
+    /// Change the threshold for a single pin
+    ///
+    /// # Panics
+    /// If `pin` is not in the range 0-15
+    pub fn set_threshold(&mut self, pin: u8, threshold: Threshold) {
+        assert!(pin < 16, "pin {} is out of range", pin);
+        self.thresholds[usize::from(pin)] = threshold;
+    }
+
+    /// The debounced level of every pin
+    pub fn state(&self) -> u16 {
+        self.stable
+    }
+
+    /// Record a new sample taken at time `now`, returning the debounced
+    /// changes. Timestamps must not decrease between samples.
+    pub fn update(&mut self, sample: u16, now: u64) -> Changes {
+        let restarted = sample ^ self.raw;
+        self.raw = sample;
+        let mut accepted = 0;
+        for pin in 0..16 {
+            let mask = 1 << pin;
+            if restarted & mask != 0 {
+                self.counts[pin] = 0;
+                self.since[pin] = now;
+            }
+            if (sample ^ self.stable) & mask == 0 {
+                continue;
+            }
+            self.counts[pin] = self.counts[pin].saturating_add(1);
+            let settled = match self.thresholds[pin] {
+                Threshold::Samples(samples) => self.counts[pin] >= samples,
+                Threshold::Time(time) => {
+                    now.saturating_sub(self.since[pin]) >= time
+                }
+            };
+            if settled {
+                accepted |= mask;
+            }
+        }
+        let previous = self.stable;
+        self.stable ^= accepted;
+        Changes::between(previous, self.stable)
+    }
+
+    /// Read the inputs from the driver and record them as a new sample
+    /// taken at time `now`
+    pub fn sample<I2C: I2c, C: Chip>(
+        &mut self,
+        tca: &mut Tca9555<I2C, C>,
+        now: u64,
+    ) -> Result<Changes, Error<I2C::Error>> {
+        Ok(self.update(tca.read_all()?, now))
+    }
+
+    /// Read the inputs from the async driver and record them as a new
+    /// sample taken at time `now`
+    #[cfg(feature = "async")]
+    pub async fn sample_async<I2C, C: Chip>(
+        &mut self,
+        tca: &mut crate::Tca9555Async<I2C, C>,
+        now: u64,
+    ) -> Result<Changes, Error<I2C::Error>>
+    where
+        I2C: embedded_hal_async::i2c::I2c,
+    {
+        Ok(self.update(tca.read_all().await?, now))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stable_count() {
+        let mut debouncer = Debouncer::new(0xffff, Threshold::Samples(3))
+            .with_threshold(1, Threshold::Samples(1));
+        assert_eq!(debouncer.update(0xfffc, 0).falling, 0x0002);
+        assert!(debouncer.update(0xfffc, 1).is_empty());
+        // A bounce on pin 0 restarts its count
+        assert!(debouncer.update(0xfffd, 2).is_empty());
+        assert!(debouncer.update(0xfffc, 3).is_empty());
+        assert!(debouncer.update(0xfffc, 4).is_empty());
+        assert_eq!(debouncer.update(0xfffc, 5).falling, 0x0001);
+        assert_eq!(debouncer.state(), 0xfffc);
+    }
+
+    #[test]
+    fn elapsed_time() {
+        let mut debouncer = Debouncer::new(0x0000, Threshold::Time(20))
+            .with_threshold(15, Threshold::Time(50));
+        assert!(debouncer.update(0x8001, 100).is_empty());
+        assert!(debouncer.update(0x8001, 115).is_empty());
+        assert_eq!(debouncer.update(0x8001, 120).rising, 0x0001);
+        // Returning to the accepted level cancels a pending change
+        assert!(debouncer.update(0x0001, 130).is_empty());
+        assert!(debouncer.update(0x8001, 140).is_empty());
+        assert!(debouncer.update(0x8001, 180).is_empty());
+        assert_eq!(debouncer.update(0x8001, 190).rising, 0x8000);
+    }
+}
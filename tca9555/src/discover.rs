@@ -0,0 +1,266 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Discovery of the expanders present on a bus.
+//!
+//! [`discover`] probes every address which a chip in the family can be
+//! strapped to, from its base address upwards, so the TCA9555 is searched
+//! for at 0x20-0x27 and the TCA9539 at 0x74-0x77. A device which
+//! acknowledges its address is only reported if its polarity inversion
+//! register for port 0 can be inverted and read back; the original value
+//! is then restored. The polarity inversion only affects the input port
+//! register, so the round-trip never changes the outputs.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::discover::discover;
+//! use tca9555::{chip, Error, Tca9555};
+//! fn slots<I2C: I2c>(mut i2c: I2C) -> Result<(), Error<I2C::Error>> {
+//!     for address in discover::<chip::Tca9555, _>(&mut i2c)? {
+//!         let mut tca = Tca9555::new(&mut i2c, address);
+//!         let inputs = tca.read_all()?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::chip::{self, Chip};
+use crate::command::*;
+use crate::registers::RegisterWrite;
+use crate::{DeviceAddr, Error};
+use embedded_hal::i2c::{self, ErrorKind, I2c};
+
+/// The addresses found by [`discover`], in ascending order
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct Discovered {
+    /// Bit `n` is set if a chip was found with address pins `n`
+    found: u8,
+}
+
+impl Discovered {
+    /// Returns `true` if a chip was found at `address`
+    pub fn contains(&self, address: DeviceAddr) -> bool {
+        self.found & (1 << address.pins()) != 0
+    }
+}
+
+impl Iterator for Discovered {
+    type Item = DeviceAddr;
+
+    fn next(&mut self) -> Option<DeviceAddr> {
+        if self.found == 0 {
+            return None;
+        }
+        let pins = self.found.trailing_zeros() as u8;
+        self.found &= !(1 << pins);
+        DeviceAddr::from_pins(pins)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.found.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Discovered {}
+
+/// The command and write for the polarity inversion register of port 0,
+/// which is at a different command on the 8-bit chips
+fn polarity_register<C: Chip>(value: u8) -> (u8, RegisterWrite) {
+    let write = RegisterWrite::single(POLARITY_INVERT_PORT_0, value);
+    let write = chip::map_write::<C>(write).unwrap_or(write);
+    (write.as_bytes()[0], write)
+}
+
+/// Treat a missing acknowledge of the first transfer to an address as no
+/// chip being present, and any other error as a bus failure
+fn not_acknowledged<E: i2c::Error>(error: E) -> Result<bool, Error<E>> {
+    match error.kind() {
+        ErrorKind::NoAcknowledge(_) => Ok(false),
+        _ => Err(Error::Bus(error)),
+    }
+}
+
+/// Probe every address which the chip `C` can have on the bus, returning
+/// the addresses of the devices which behave like it. Addresses which do
+/// not acknowledge the first transfer are skipped.
+///
+/// # Errors
+/// Any other bus error is returned, including a missing acknowledge later
+/// in the round-trip, since it may have interrupted the round-trip before
+/// the polarity inversion register was restored.
+pub fn discover<C: Chip, I2C: I2c>(
+    i2c: &mut I2C,
+) -> Result<Discovered, Error<I2C::Error>> {
+    let mut found = 0;
+    for device in DeviceAddr::all().take(1 << C::ADDRESS_PINS) {
+        let address = chip::address::<C>(device);
+        if probe::<C, _>(i2c, address)? {
+            trace!("{=str} found at {=u8:#04x}", C::NAME, address);
+            found |= 1 << device.pins();
+        }
+    }
+    Ok(Discovered { found })
+}
+
+/// Invert the polarity inversion register at `address`, read it back and
+/// restore it
+fn probe<C: Chip, I2C: I2c>(
+    i2c: &mut I2C,
+    address: u8,
+) -> Result<bool, Error<I2C::Error>> {
+    let (command, _) = polarity_register::<C>(0);
+    let mut original = [0];
+    if let Err(error) = i2c.write_read(address, &[command], &mut original) {
+        return not_acknowledged(error);
+    }
+    let (_, inverted) = polarity_register::<C>(!original[0]);
+    i2c.write(address, inverted.as_bytes())
+        .map_err(Error::Bus)?;
+    let mut readback = [0];
+    i2c.write_read(address, &[command], &mut readback)
+        .map_err(Error::Bus)?;
+    let (_, restore) = polarity_register::<C>(original[0]);
+    i2c.write(address, restore.as_bytes()).map_err(Error::Bus)?;
+    Ok(readback[0] == !original[0])
+}
+
+#[cfg(feature = "async")]
+mod asynch {
+    use super::*;
+    use embedded_hal_async::i2c::I2c;
+
+    /// Probe every address which the chip `C` can have on the bus, as
+    /// [`discover`] does for a blocking bus
+    ///
+    /// # Errors
+    /// Bus errors other than a missing acknowledge of the first transfer
+    /// are returned
+    pub async fn discover_async<C: Chip, I2C: I2c>(
+        i2c: &mut I2C,
+    ) -> Result<Discovered, Error<I2C::Error>> {
+        let mut found = 0;
+        for device in DeviceAddr::all().take(1 << C::ADDRESS_PINS) {
+            let address = chip::address::<C>(device);
+            if probe::<C, _>(i2c, address).await? {
+                trace!("{=str} found at {=u8:#04x}", C::NAME, address);
+                found |= 1 << device.pins();
+            }
+        }
+        Ok(Discovered { found })
+    }
+
+    async fn probe<C: Chip, I2C: I2c>(
+        i2c: &mut I2C,
+        address: u8,
+    ) -> Result<bool, Error<I2C::Error>> {
+        let (command, _) = polarity_register::<C>(0);
+        let mut original = [0];
+        let read = i2c.write_read(address, &[command], &mut original).await;
+        if let Err(error) = read {
+            return not_acknowledged(error);
+        }
+        let (_, inverted) = polarity_register::<C>(!original[0]);
+        i2c.write(address, inverted.as_bytes())
+            .await
+            .map_err(Error::Bus)?;
+        let mut readback = [0];
+        i2c.write_read(address, &[command], &mut readback)
+            .await
+            .map_err(Error::Bus)?;
+        let (_, restore) = polarity_register::<C>(original[0]);
+        i2c.write(address, restore.as_bytes())
+            .await
+            .map_err(Error::Bus)?;
+        Ok(readback[0] == !original[0])
+    }
+}
+
+#[cfg(feature = "async")]
+pub use asynch::discover_async;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal::i2c::NoAcknowledgeSource;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    fn absent(address: u8) -> Transaction {
+        Transaction::write_read(address, vec![POLARITY_INVERT_PORT_0], vec![0])
+            .with_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+    }
+
+    #[test]
+    fn round_trip_confirms_chip() {
+        let expectations = [
+            absent(0x74),
+            // A TCA9539 at 0x75
+            Transaction::write_read(0x75, vec![0x04], vec![0x00]),
+            Transaction::write(0x75, vec![0x04, 0xff]),
+            Transaction::write_read(0x75, vec![0x04], vec![0xff]),
+            Transaction::write(0x75, vec![0x04, 0x00]),
+            // Some other device at 0x76, which ignores the write
+            Transaction::write_read(0x76, vec![0x04], vec![0x5a]),
+            Transaction::write(0x76, vec![0x04, 0xa5]),
+            Transaction::write_read(0x76, vec![0x04], vec![0x5a]),
+            Transaction::write(0x76, vec![0x04, 0x5a]),
+            absent(0x77),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let found = discover::<chip::Tca9539, _>(&mut i2c).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found.contains(DeviceAddr::Alternative(true, false, false)));
+        assert_eq!(
+            found.collect::<Vec<_>>(),
+            [DeviceAddr::Alternative(true, false, false)]
+        );
+        i2c.done();
+    }
+
+    #[test]
+    fn bus_error_returned() {
+        let mut i2c =
+            Mock::new(&[Transaction::write_read(0x20, vec![0x02], vec![0])
+                .with_error(ErrorKind::ArbitrationLoss)]);
+        assert_eq!(
+            discover::<chip::Pca9554, _>(&mut i2c),
+            Err(Error::Bus(ErrorKind::ArbitrationLoss))
+        );
+        i2c.done();
+    }
+
+    #[test]
+    fn late_missing_acknowledge_returned() {
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data);
+        let expectations = [
+            absent(0x74),
+            Transaction::write_read(0x75, vec![0x04], vec![0x00]),
+            Transaction::write(0x75, vec![0x04, 0xff]).with_error(nack),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        assert_eq!(
+            discover::<chip::Tca9539, _>(&mut i2c),
+            Err(Error::Bus(nack))
+        );
+        i2c.done();
+    }
+
+    #[cfg(feature = "sim")]
+    #[test]
+    fn chips_on_simulated_bus() {
+        use crate::sim::{SimI2c, Tca9555Sim};
+        let chips = [
+            Tca9555Sim::new(DeviceAddr::Default),
+            Tca9555Sim::new(DeviceAddr::Alternative(true, true, true)),
+        ];
+        let mut i2c = SimI2c::new(&chips);
+        let found = discover::<chip::Tca9555, _>(&mut i2c).unwrap();
+        assert_eq!(
+            found.map(DeviceAddr::addr).collect::<Vec<_>>(),
+            [0x20, 0x27]
+        );
+        assert_eq!(chips[1].registers(), crate::Registers::POWER_ON);
+    }
+}
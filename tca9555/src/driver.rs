@@ -0,0 +1,489 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Register access methods shared by the blocking and async drivers
+
+/// Pass a bus transaction through unchanged, as the blocking driver's
+/// transactions have completed when they return
+macro_rules! blocking {
+    ($transaction:expr) => {
+        $transaction
+    };
+}
+
+/// Await a bus transaction
+#[cfg(feature = "async")]
+macro_rules! awaited {
+    ($transaction:expr) => {
+        $transaction.await
+    };
+}
+
+/// Define the register access methods of a driver. The blocking and async
+/// drivers differ only in whether bus transactions are awaited, so both
+/// are generated from this one definition: `$io` is a macro which turns a
+/// call performing a bus transaction into its result, and `$async` is
+/// given as `async` for the async driver.
+///
+/// The names used in the methods, such as `I2c`, `Chip` and the register
+/// commands, are resolved where the macro is invoked. The modules which add
+/// methods to both drivers, such as `agile`, define their methods with
+/// macros of the same form.
+macro_rules! driver_methods {
+    ($driver:ident, $io:ident $(, $async:tt)?) => {
+        impl<I2C, E, C: Chip> $driver<I2C, C>
+        where
+            I2C: I2c<Error = E>,
+        {
+            /// Read consecutive registers in a single transaction
+            $($async)? fn read_registers(
+                &mut self,
+                register: u8,
+                buffer: &mut [u8],
+            ) -> Result<(), Error<E>> {
+                let address = chip::address::<C>(self.address);
+                let Some((command, len)) =
+                    chip::map_read::<C>(register, buffer)
+                else {
+                    return Ok(());
+                };
+                let buffer = &mut buffer[..len];
+                $io!(self.i2c.write_read(address, &[command], buffer))
+                    .map_err(Error::Bus)?;
+                trace!(
+                    "{=str} {=u8:#04x} read {=u8:#04x}: {=[u8]:#04x}",
+                    C::NAME,
+                    address,
+                    command,
+                    buffer
+                );
+                Ok(())
+            }
+
+            pub(crate) $($async)? fn read_register(
+                &mut self,
+                register: u8,
+            ) -> Result<u8, Error<E>> {
+                let mut value = [0];
+                $io!(self.read_registers(register, &mut value))?;
+                Ok(value[0])
+            }
+
+            /// Read both registers of a pair in a single transaction, so
+            /// that the two ports are sampled at the same instant
+            pub(crate) $($async)? fn read_register_pair(
+                &mut self,
+                register: u8,
+            ) -> Result<u16, Error<E>> {
+                let mut value = [0; 2];
+                $io!(self.read_registers(register, &mut value))?;
+                Ok(u16::from_le_bytes(value))
+            }
+
+            /// Perform a register write without updating the cache
+            pub(crate) $($async)? fn write_register(
+                &mut self,
+                write: RegisterWrite,
+            ) -> Result<(), Error<E>> {
+                let Some(mapped) = chip::map_write::<C>(write) else {
+                    return Ok(());
+                };
+                let address = chip::address::<C>(self.address);
+                $io!(self.i2c.write(address, mapped.as_bytes()))
+                    .map_err(Error::Bus)?;
+                trace!(
+                    "{=str} {=u8:#04x} write {=[u8]:#04x}",
+                    C::NAME,
+                    address,
+                    mapped.as_bytes()
+                );
+                Ok(())
+            }
+
+            /// Perform a register write and update the cached copy to match
+            $($async)? fn write_cached(
+                &mut self,
+                write: RegisterWrite,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_register(write))?;
+                self.registers.apply(&write);
+                self.registers.restrict(C::PIN_MASK);
+                Ok(())
+            }
+
+            $($async)? fn modify_cached(
+                &mut self,
+                register: u8,
+                mask: u16,
+                value: u16,
+            ) -> Result<(), Error<E>> {
+                let write = self.registers.modify(register, mask, value);
+                $io!(self.write_cached(write))
+            }
+
+            /// Read the output, polarity inversion and configuration
+            /// registers back from the chip, replacing the cached copy
+            pub $($async)? fn refresh_registers(
+                &mut self,
+            ) -> Result<Registers, Error<E>> {
+                self.registers = Registers {
+                    output: $io!(self.read_output_all())?,
+                    polarity_invert: $io!(self.read_polarity_invert_all())?,
+                    direction: $io!(self.read_direction_all())?,
+                };
+                Ok(self.registers)
+            }
+
+            /// Read back the output, polarity inversion and configuration
+            /// registers and check that they match the cached copy,
+            /// returning [`Error::VerifyFailed`] for the first mismatch
+            pub $($async)? fn verify_registers(
+                &mut self,
+            ) -> Result<(), Error<E>> {
+                let registers = self.registers;
+                for (register, expected) in [
+                    (WRITE_PORT_0, registers.output),
+                    (POLARITY_INVERT_PORT_0, registers.polarity_invert),
+                    (CONFIGURATION_PORT_0, registers.direction),
+                ] {
+                    let actual = $io!(self.read_register_pair(register))?;
+                    if actual != expected {
+                        return Err(Error::VerifyFailed {
+                            register,
+                            expected,
+                            actual,
+                        });
+                    }
+                }
+                Ok(())
+            }
+
+            /// Set the output pins selected by `mask` to the corresponding
+            /// bits of `value`, leaving the others unchanged. This is done
+            /// against the register cache, so only a single I2C write is
+            /// performed.
+            pub $($async)? fn modify_outputs(
+                &mut self,
+                mask: u16,
+                value: u16,
+            ) -> Result<(), Error<E>> {
+                $io!(self.modify_cached(WRITE_PORT_0, mask, value))
+            }
+
+            /// Set the directions of the pins selected by `mask` to the
+            /// corresponding bits of `value`, where 1 is an input and 0 an
+            /// output, leaving the others unchanged. Only the ports which
+            /// contain selected pins are written.
+            pub $($async)? fn modify_directions(
+                &mut self,
+                mask: u16,
+                value: u16,
+            ) -> Result<(), Error<E>> {
+                $io!(self.modify_cached(CONFIGURATION_PORT_0, mask, value))
+            }
+
+            /// Drive a single output pin high. Pins 0-7 are port 0 and pins
+            /// 8-15 are port 1.
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15 (0-7 on the 8-bit chips), or [`Error::PinIsInput`] if
+            /// the pin is configured as an input. Use
+            /// [`modify_outputs`](Self::modify_outputs) to preload the level
+            /// of an input before switching it to an output.
+            pub $($async)? fn set_pin_high(
+                &mut self,
+                pin: u8,
+            ) -> Result<(), Error<E>> {
+                let mask = self.registers.output_mask(pin, C::PINS)?;
+                $io!(self.modify_outputs(mask, 0xffff))
+            }
+
+            /// Drive a single output pin low. Pins 0-7 are port 0 and pins
+            /// 8-15 are port 1.
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15 (0-7 on the 8-bit chips), or [`Error::PinIsInput`] if
+            /// the pin is configured as an input. Use
+            /// [`modify_outputs`](Self::modify_outputs) to preload the level
+            /// of an input before switching it to an output.
+            pub $($async)? fn set_pin_low(
+                &mut self,
+                pin: u8,
+            ) -> Result<(), Error<E>> {
+                let mask = self.registers.output_mask(pin, C::PINS)?;
+                $io!(self.modify_outputs(mask, 0x0000))
+            }
+
+            /// Toggle a single output pin. Pins 0-7 are port 0 and pins
+            /// 8-15 are port 1.
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15 (0-7 on the 8-bit chips), or [`Error::PinIsInput`] if
+            /// the pin is configured as an input. Use
+            /// [`modify_outputs`](Self::modify_outputs) to preload the level
+            /// of an input before switching it to an output.
+            pub $($async)? fn toggle_pin(
+                &mut self,
+                pin: u8,
+            ) -> Result<(), Error<E>> {
+                let mask = self.registers.output_mask(pin, C::PINS)?;
+                let levels = !self.registers.output;
+                $io!(self.modify_outputs(mask, levels))
+            }
+
+            /// Configure a single pin as an output. The pin will
+            /// immediately drive the level held in the output register.
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15 (0-7 on the 8-bit chips)
+            pub $($async)? fn set_pin_as_output(
+                &mut self,
+                pin: u8,
+            ) -> Result<(), Error<E>> {
+                let mask = pin_mask(pin, C::PINS)?;
+                $io!(self.modify_cached(CONFIGURATION_PORT_0, mask, 0x0000))
+            }
+
+            /// Configure a single pin as an input
+            ///
+            /// # Errors
+            /// Returns [`Error::InvalidPin`] if `pin` is not in the range
+            /// 0-15 (0-7 on the 8-bit chips)
+            pub $($async)? fn set_pin_as_input(
+                &mut self,
+                pin: u8,
+            ) -> Result<(), Error<E>> {
+                let mask = pin_mask(pin, C::PINS)?;
+                $io!(self.modify_cached(CONFIGURATION_PORT_0, mask, 0xffff))
+            }
+
+            /// Read input port 0 in full, returning a u8. Reads the current
+            /// logic level of the pins, regardless of whether they have been
+            /// configured as inputs or outputs
+            pub $($async)? fn read_port_0(&mut self) -> Result<u8, Error<E>> {
+                $io!(self.read_port(0))
+            }
+
+            /// Read a single input port, recording it in the last inputs
+            pub(crate) $($async)? fn read_port(
+                &mut self,
+                port: u8,
+            ) -> Result<u8, Error<E>> {
+                let register =
+                    if port == 0 { READ_PORT_0 } else { READ_PORT_1 };
+                let value = $io!(self.read_register(register))?;
+                self.inputs = replace_port(self.inputs, port, value);
+                Ok(value)
+            }
+
+            /// Read both input ports in a single transaction, combining
+            /// their values into a u16. Reads the current logic level of
+            /// the pins, regardless of whether they have been configured as
+            /// inputs or outputs
+            pub $($async)? fn read_all(&mut self) -> Result<u16, Error<E>> {
+                self.inputs = $io!(self.read_register_pair(READ_PORT_0))?;
+                Ok(self.inputs)
+            }
+
+            /// Read back the port 0 output register. This is the level that
+            /// output pins are driven to, not necessarily the level on the
+            /// pins.
+            pub $($async)? fn read_output_port_0(
+                &mut self,
+            ) -> Result<u8, Error<E>> {
+                $io!(self.read_register(WRITE_PORT_0))
+            }
+
+            /// Read back both output registers, combining their values into
+            /// a u16
+            pub $($async)? fn read_output_all(
+                &mut self,
+            ) -> Result<u16, Error<E>> {
+                $io!(self.read_register_pair(WRITE_PORT_0))
+            }
+
+            /// Read back the port 0 polarity inversion register
+            pub $($async)? fn read_polarity_invert_0(
+                &mut self,
+            ) -> Result<u8, Error<E>> {
+                $io!(self.read_register(POLARITY_INVERT_PORT_0))
+            }
+
+            /// Read back both polarity inversion registers, combining their
+            /// values into a u16
+            pub $($async)? fn read_polarity_invert_all(
+                &mut self,
+            ) -> Result<u16, Error<E>> {
+                $io!(self.read_register_pair(POLARITY_INVERT_PORT_0))
+            }
+
+            /// Read back the port 0 direction register. Bits set to 0 are
+            /// in output mode, while bits set to 1 are in input mode.
+            pub $($async)? fn read_direction_0(
+                &mut self,
+            ) -> Result<u8, Error<E>> {
+                $io!(self.read_register(CONFIGURATION_PORT_0))
+            }
+
+            /// Read back both direction registers, combining their values
+            /// into a u16
+            pub $($async)? fn read_direction_all(
+                &mut self,
+            ) -> Result<u16, Error<E>> {
+                $io!(self.read_register_pair(CONFIGURATION_PORT_0))
+            }
+
+            /// Write the given byte to port 0. Has no effect on pins which
+            /// have been configured as inputs.
+            pub $($async)? fn write_port_0(
+                &mut self,
+                value: u8,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::single(
+                    WRITE_PORT_0,
+                    value
+                )))
+            }
+
+            /// Set the port 0 direction register. Bits set to 0 are in
+            /// output mode, while bits set to 1 are in input mode.
+            pub $($async)? fn set_port_0_direction(
+                &mut self,
+                dir_mask: u8,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::single(
+                    CONFIGURATION_PORT_0,
+                    dir_mask
+                )))
+            }
+
+            /// Set the port 0 polarity inversion register. Bits set to 1
+            /// have their polarity inverted
+            pub $($async)? fn set_port_0_polarity_invert(
+                &mut self,
+                polarity_mask: u8,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::single(
+                    POLARITY_INVERT_PORT_0,
+                    polarity_mask
+                )))
+            }
+
+            /// Write the given u16 across all 16 output pins. Both ports are
+            /// written in a single transaction, so all outputs change
+            /// together.
+            pub $($async)? fn write_all(
+                &mut self,
+                value: u16,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::pair(
+                    WRITE_PORT_0,
+                    value
+                )))
+            }
+
+            /// Set both direction registers in a single transaction. Bits
+            /// set to 0 are in output mode, while bits set to 1 are in
+            /// input mode.
+            pub $($async)? fn set_direction_all(
+                &mut self,
+                dir_mask: u16,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::pair(
+                    CONFIGURATION_PORT_0,
+                    dir_mask
+                )))
+            }
+
+            /// Set both polarity inversion registers in a single
+            /// transaction. Bits set to 1 have their polarity inverted
+            pub $($async)? fn set_polarity_invert_all(
+                &mut self,
+                polarity_mask: u16,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::pair(
+                    POLARITY_INVERT_PORT_0,
+                    polarity_mask
+                )))
+            }
+        }
+
+        /// Methods for port 1, which only the 16-bit chips have
+        impl<I2C, E, C: TwoPorts> $driver<I2C, C>
+        where
+            I2C: I2c<Error = E>,
+        {
+            /// Read input port 1 in full, returning a u8. Reads the current
+            /// logic level of the pins, regardless of whether they have been
+            /// configured as inputs or outputs
+            pub $($async)? fn read_port_1(&mut self) -> Result<u8, Error<E>> {
+                $io!(self.read_port(1))
+            }
+
+            /// Read back the port 1 output register. This is the level that
+            /// output pins are driven to, not necessarily the level on the
+            /// pins.
+            pub $($async)? fn read_output_port_1(
+                &mut self,
+            ) -> Result<u8, Error<E>> {
+                $io!(self.read_register(WRITE_PORT_1))
+            }
+
+            /// Read back the port 1 polarity inversion register
+            pub $($async)? fn read_polarity_invert_1(
+                &mut self,
+            ) -> Result<u8, Error<E>> {
+                $io!(self.read_register(POLARITY_INVERT_PORT_1))
+            }
+
+            /// Read back the port 1 direction register. Bits set to 0 are
+            /// in output mode, while bits set to 1 are in input mode.
+            pub $($async)? fn read_direction_1(
+                &mut self,
+            ) -> Result<u8, Error<E>> {
+                $io!(self.read_register(CONFIGURATION_PORT_1))
+            }
+
+            /// Write the given byte to port 1. Has no effect on pins which
+            /// have been configured as inputs.
+            pub $($async)? fn write_port_1(
+                &mut self,
+                value: u8,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::single(
+                    WRITE_PORT_1,
+                    value
+                )))
+            }
+
+            /// Set the port 1 direction register. Bits set to 0 are in
+            /// output mode, while bits set to 1 are in input mode.
+            pub $($async)? fn set_port_1_direction(
+                &mut self,
+                dir_mask: u8,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::single(
+                    CONFIGURATION_PORT_1,
+                    dir_mask
+                )))
+            }
+
+            /// Set the port 1 polarity inversion register. Bits set to 1
+            /// have their polarity inverted
+            pub $($async)? fn set_port_1_polarity_invert(
+                &mut self,
+                polarity_mask: u8,
+            ) -> Result<(), Error<E>> {
+                $io!(self.write_cached(RegisterWrite::single(
+                    POLARITY_INVERT_PORT_1,
+                    polarity_mask
+                )))
+            }
+        }
+    };
+}
@@ -0,0 +1,299 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Quadrature rotary encoder decoding.
+//!
+//! Each [`Encoder`] decodes the A and B signals on a pair of pins from
+//! samples of the inputs. Samples must be taken often enough to see every
+//! transition, either by polling or when the INT output reports a change.
+//! A transition in which both signals change at once has skipped a state,
+//! so its direction is unknown; it is ignored and counted in
+//! [`Encoder::invalid_transitions`].
+//!
+//! Several encoders on one chip can be decoded from the same sample with
+//! [`Encoders`].
+//!
+//! ```no_run
+//! use embedded_hal::digital::InputPin;
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::encoder::{Detent, Encoder, Encoders};
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn knobs<I2C: I2c>(
+//!     i2c: I2C,
+//!     mut int: impl InputPin,
+//! ) -> Result<(), Error<I2C::Error>> {
+//!     let mut tca = Tca9555::new(i2c, DeviceAddr::default());
+//!     let mut encoders = Encoders::new([
+//!         Encoder::new(0, 1, Detent::Full),
+//!         Encoder::new(2, 3, Detent::Half),
+//!     ]);
+//!     loop {
+//!         if tca.poll_interrupt(&mut int)?.is_some() {
+//!             encoders.update(tca.last_inputs());
+//!             let [volume, balance] = encoders.positions();
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::chip::Chip;
+use crate::{Error, Tca9555};
+use embedded_hal::i2c::I2c;
+
+/// Number of quadrature states between the detents of an encoder, which
+/// is the number of transitions counted as one step
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Detent {
+    /// One step per full quadrature cycle, resting with both signals high
+    Full,
+    /// One step per half cycle, resting with both signals equal
+    Half,
+    /// One step per transition
+    Quarter,
+}
+
+impl Detent {
+    fn transitions(self) -> i8 {
+        match self {
+            Self::Full => 4,
+            Self::Half => 2,
+            Self::Quarter => 1,
+        }
+    }
+
+    fn is_rest(self, state: u8) -> bool {
+        match self {
+            Self::Full => state == 0b11,
+            Self::Half => state == 0b00 || state == 0b11,
+            Self::Quarter => true,
+        }
+    }
+}
+
+/// Direction of a step
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// A leads B, increasing the position. Swap the pins if this is the
+    /// wrong way round for an encoder.
+    Clockwise,
+    /// B leads A, decreasing the position
+    CounterClockwise,
+}
+
+/// Change in position for each transition, indexed by the previous state
+/// and the new state, where each state is `A << 1 | B`. Zero for no
+/// change, and for invalid transitions where both signals changed.
+const TRANSITIONS: [i8; 16] =
+    [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+
+/// Decoder for a single encoder
+#[derive(Copy, Clone, Debug)]
+pub struct Encoder {
+    a: u8,
+    b: u8,
+    detent: Detent,
+    /// Signal state at the previous sample, or `None` before the first
+    state: Option<u8>,
+    /// Transitions counted towards the next step
+    partial: i8,
+    position: i32,
+    direction: Option<Direction>,
+    invalid: u32,
+}
+
+impl Encoder {
+    /// Create a decoder for an encoder with its A signal on pin `a` and B
+    /// signal on pin `b`. The first sample sets the starting state without
+    /// counting a step.
+    ///
+    /// # Panics
+    /// If either pin is out of range, or both are the same pin
+    pub fn new(a: u8, b: u8, detent: Detent) -> Self {
+        assert!(a < 16 && b < 16, "encoder pins must be in the range 0-15");
+        assert!(a != b, "encoder pins must be different");
+        Self {
+            a,
+            b,
+            detent,
+            state: None,
+            partial: 0,
+            position: 0,
+            direction: None,
+            invalid: 0,
+        }
+    }
+
+    /// Accumulated position, in steps
+    pub fn position(&self) -> i32 {
+        self.position
+    }
+
+    /// Set the accumulated position
+    pub fn set_position(&mut self, position: i32) {
+        self.position = position;
+    }
+
+    /// Direction of the most recent step
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    /// Number of transitions which were ignored because both signals
+    /// changed between samples
+    pub fn invalid_transitions(&self) -> u32 {
+        self.invalid
+    }
+
+    /// Record a new sample of the inputs, returning the direction if the
+    /// encoder moved a whole step
+    pub fn update(&mut self, sample: u16) -> Option<Direction> {
+        let bit = |pin: u8| (sample >> pin) as u8 & 1;
+        let state = bit(self.a) << 1 | bit(self.b);
+        let previous = self.state.replace(state)?;
+        if previous == state {
+            return None;
+        }
+        if previous ^ state == 0b11 {
+            self.invalid = self.invalid.wrapping_add(1);
+            self.partial = 0;
+            return None;
+        }
+        self.partial += TRANSITIONS[usize::from(previous << 2 | state)];
+        let step = if self.partial >= self.detent.transitions() {
+            Some(Direction::Clockwise)
+        } else if self.partial <= -self.detent.transitions() {
+            Some(Direction::CounterClockwise)
+        } else {
+            None
+        };
+        if step.is_some() || self.detent.is_rest(state) {
+            self.partial = 0;
+        }
+        match step {
+            Some(Direction::Clockwise) => self.position += 1,
+            Some(Direction::CounterClockwise) => self.position -= 1,
+            None => return None,
+        }
+        self.direction = step;
+        step
+    }
+}
+
+/// Several encoders on one chip, decoded from the same samples
+#[derive(Copy, Clone, Debug)]
+pub struct Encoders<const N: usize> {
+    encoders: [Encoder; N],
+}
+
+impl<const N: usize> Encoders<N> {
+    /// Group the encoders, which should all use different pins
+    pub fn new(encoders: [Encoder; N]) -> Self {
+        Self { encoders }
+    }
+
+    /// The decoder for each encoder
+    pub fn encoders(&self) -> &[Encoder; N] {
+        &self.encoders
+    }
+
+    /// Mutable access to the decoder for each encoder, for example to reset
+    /// positions
+    pub fn encoders_mut(&mut self) -> &mut [Encoder; N] {
+        &mut self.encoders
+    }
+
+    /// Accumulated position of each encoder
+    pub fn positions(&self) -> [i32; N] {
+        self.encoders.map(|encoder| encoder.position())
+    }
+
+    /// Record a new sample of the inputs, returning the step made by each
+    /// encoder
+    pub fn update(&mut self, sample: u16) -> [Option<Direction>; N] {
+        let mut steps = [None; N];
+        for (step, encoder) in steps.iter_mut().zip(&mut self.encoders) {
+            *step = encoder.update(sample);
+        }
+        steps
+    }
+
+    /// Read the inputs from the driver and record them as a new sample
+    pub fn sample<I2C: I2c, C: Chip>(
+        &mut self,
+        tca: &mut Tca9555<I2C, C>,
+    ) -> Result<[Option<Direction>; N], Error<I2C::Error>> {
+        Ok(self.update(tca.read_all()?))
+    }
+
+    /// Read the inputs from the async driver and record them as a new
+    /// sample
+    #[cfg(feature = "async")]
+    pub async fn sample_async<I2C, C: Chip>(
+        &mut self,
+        tca: &mut crate::Tca9555Async<I2C, C>,
+    ) -> Result<[Option<Direction>; N], Error<I2C::Error>>
+    where
+        I2C: embedded_hal_async::i2c::I2c,
+    {
+        Ok(self.update(tca.read_all().await?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Samples with A on pin 0 and B on pin 1, one clockwise cycle
+    const CLOCKWISE: [u16; 4] = [0b01, 0b00, 0b10, 0b11];
+
+    #[test]
+    fn full_step() {
+        let mut encoder = Encoder::new(0, 1, Detent::Full);
+        assert_eq!(encoder.update(0b11), None);
+        for &sample in &CLOCKWISE[..3] {
+            assert_eq!(encoder.update(sample), None);
+        }
+        assert_eq!(encoder.update(CLOCKWISE[3]), Some(Direction::Clockwise));
+        for &sample in CLOCKWISE.iter().rev().skip(1) {
+            encoder.update(sample);
+        }
+        assert_eq!(encoder.update(0b11), Some(Direction::CounterClockwise));
+        assert_eq!(encoder.position(), 0);
+        assert_eq!(encoder.direction(), Some(Direction::CounterClockwise));
+    }
+
+    #[test]
+    fn invalid_transitions_rejected() {
+        let mut encoder = Encoder::new(0, 1, Detent::Quarter);
+        encoder.update(0b11);
+        assert_eq!(encoder.update(0b00), None);
+        assert_eq!(encoder.invalid_transitions(), 1);
+        assert_eq!(encoder.update(0b10), Some(Direction::Clockwise));
+        assert_eq!(encoder.position(), 1);
+    }
+
+    #[test]
+    fn several_encoders() {
+        // Half steps on pins 4 and 5, quarter steps on pins 9 and 8
+        let mut encoders = Encoders::new([
+            Encoder::new(4, 5, Detent::Half),
+            Encoder::new(9, 8, Detent::Quarter),
+        ]);
+        encoders.update(0xffff);
+        assert_eq!(
+            encoders.update(0xfddf),
+            [None, Some(Direction::CounterClockwise)]
+        );
+        assert_eq!(
+            encoders.update(0xfccf),
+            [
+                Some(Direction::Clockwise),
+                Some(Direction::CounterClockwise)
+            ]
+        );
+        assert_eq!(encoders.positions(), [1, -2]);
+    }
+}
@@ -0,0 +1,144 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver error type
+
+use crate::DeviceAddr;
+use core::fmt::{self, Debug, Display};
+use embedded_hal::digital::{self, ErrorKind};
+
+/// Error returned by the driver, wrapping the underlying I2C error `E`
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Error on the I2C bus
+    Bus(E),
+    /// The pin index is not a pin of the chip
+    InvalidPin(u8),
+    /// The pin is configured as an input, so cannot be driven
+    PinIsInput(u8),
+    /// A register pair read back from the chip did not match the value
+    /// which was written to it
+    VerifyFailed {
+        /// Port 0 register of the pair
+        register: u8,
+        /// Value which was written
+        expected: u16,
+        /// Value which was read back
+        actual: u16,
+    },
+    /// Error reading the INT pin
+    IntPin(ErrorKind),
+    /// Error driving the RESET pin
+    ResetPin(ErrorKind),
+    /// The address is not valid for the chip
+    Address(AddrError),
+}
+
+impl<E> Error<E> {
+    pub(crate) fn int_pin(error: impl digital::Error) -> Self {
+        Self::IntPin(error.kind())
+    }
+
+    pub(crate) fn reset_pin(error: impl digital::Error) -> Self {
+        Self::ResetPin(error.kind())
+    }
+}
+
+impl<E: Debug> Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bus(error) => write!(f, "I2C bus error: {:?}", error),
+            Self::InvalidPin(pin) => write!(f, "pin {} is out of range", pin),
+            Self::PinIsInput(pin) => {
+                write!(f, "pin {} is configured as an input", pin)
+            }
+            Self::VerifyFailed {
+                register,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "register {:#04x} read back as {:#06x}, expected {:#06x}",
+                register, actual, expected
+            ),
+            Self::IntPin(kind) => write!(f, "INT pin error: {}", kind),
+            Self::ResetPin(kind) => write!(f, "RESET pin error: {}", kind),
+            Self::Address(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl<E> From<AddrError> for Error<E> {
+    fn from(error: AddrError) -> Self {
+        Self::Address(error)
+    }
+}
+
+impl<E: Debug> digital::Error for Error<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::IntPin(kind) | Self::ResetPin(kind) => *kind,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Error converting a raw address or a string into a [`DeviceAddr`], or
+/// using an address which the chip does not support
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum AddrError {
+    /// The raw address is not one which the chip can be strapped to, such
+    /// as 0x20-0x27 for a TCA9555
+    OutOfRange(u8),
+    /// The string is neither a hexadecimal address such as `0x23` nor a
+    /// list of address pins such as `A0=1,A1=1`
+    Syntax,
+    /// The address uses an address pin which the chip does not have, such
+    /// as A2 on a TCA9539
+    UnsupportedPins(DeviceAddr),
+    /// The same address was given for more than one chip
+    Duplicate(DeviceAddr),
+}
+
+impl Display for AddrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange(address) => {
+                write!(f, "address {:#04x} is out of the chip's range", address)
+            }
+            Self::Syntax => write!(f, "invalid address"),
+            Self::UnsupportedPins(address) => {
+                write!(f, "{} uses an address pin the chip lacks", address)
+            }
+            Self::Duplicate(address) => {
+                write!(f, "{} is used by more than one chip", address)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let error: Error<()> = Error::VerifyFailed {
+            register: 0x06,
+            expected: 0xff00,
+            actual: 0xffff,
+        };
+        assert_eq!(
+            error.to_string(),
+            "register 0x06 read back as 0xffff, expected 0xff00"
+        );
+        assert_eq!(Error::Bus(()).to_string(), "I2C bus error: ()");
+        assert_eq!(
+            AddrError::OutOfRange(0x74).to_string(),
+            "address 0x74 is out of the chip's range"
+        );
+    }
+}
@@ -0,0 +1,440 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Button gestures: clicks, double-clicks, long presses and auto-repeat.
+//!
+//! A [`GestureDetector`] runs a state machine for each button pin, fed
+//! with periodic samples of the inputs and a timestamp from a monotonic
+//! clock supplied by the caller. Buttons are pressed when their pin is low;
+//! active high buttons can be handled by setting the polarity inversion
+//! register for their pins. Samples should already be debounced, for
+//! example with the [`debounce`](crate::debounce) module, or taken at an
+//! interval longer than the switches bounce for.
+//!
+//! A short press and release is reported as a click once the double-click
+//! window has passed without a second press, so a double-click is never
+//! also reported as a click. Holding a button for the long-press time
+//! reports a long press instead of a click, followed by repeats at the
+//! auto-repeat interval until it is released. If the second press of a
+//! double-click is held instead, the first press is reported as a click
+//! along with the long press.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::gesture::{Gesture, GestureDetector, Timings};
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn buttons<I2C: I2c>(
+//!     i2c: I2C,
+//!     now_ms: impl Fn() -> u64,
+//! ) -> Result<(), Error<I2C::Error>> {
+//!     let mut tca = Tca9555::new(i2c, DeviceAddr::default());
+//!     // Buttons on pins 0-3, using the default timings in milliseconds
+//!     let mut detector = GestureDetector::new(0x000f, Timings::default());
+//!     loop {
+//!         for event in detector.sample(&mut tca, now_ms())?.events() {
+//!             match event.gesture {
+//!                 Gesture::Click => { /* button `event.pin` was clicked */ }
+//!                 Gesture::LongPress => { /* ... */ }
+//!                 _ => {}
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::chip::Chip;
+use crate::{Error, Tca9555};
+use embedded_hal::i2c::I2c;
+
+/// Timings used to recognise gestures, in the units of the timestamps
+/// passed to [`GestureDetector::update`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct Timings {
+    /// Longest time between releasing a button and pressing it again for
+    /// the two presses to form a double-click. Zero disables double-clicks,
+    /// so clicks are reported as soon as the button is released.
+    pub double_click: u64,
+    /// Time a button must be held to be reported as a long press
+    pub long_press: u64,
+    /// Interval between repeats while a button is held after a long press,
+    /// or `None` to disable auto-repeat
+    pub repeat_interval: Option<u64>,
+}
+
+impl Default for Timings {
+    /// Timings suitable for timestamps in milliseconds: a 300ms
+    /// double-click window, 800ms long press and 200ms repeat interval
+    fn default() -> Self {
+        Self {
+            double_click: 300,
+            long_press: 800,
+            repeat_interval: Some(200),
+        }
+    }
+}
+
+/// A gesture made with a single button
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Gesture {
+    /// The button was pressed
+    Press,
+    /// The button was released
+    Release,
+    /// The button was pressed and released once
+    Click,
+    /// The button was pressed and released twice within the double-click
+    /// window
+    DoubleClick,
+    /// The button has been held for the long-press time
+    LongPress,
+    /// The button is still held after a long press
+    Repeat,
+}
+
+/// A gesture on a single pin
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct GestureEvent {
+    /// Index of the pin, where pins 0-7 are port 0 and 8-15 are port 1
+    pub pin: u8,
+    /// The gesture made
+    pub gesture: Gesture,
+}
+
+/// The gestures recognised from one sample. Bit `n` of each mask
+/// corresponds to pin `n`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct Gestures {
+    /// Buttons which were pressed
+    pub press: u16,
+    /// Buttons which were released
+    pub release: u16,
+    /// Buttons which were clicked
+    pub click: u16,
+    /// Buttons which were double-clicked
+    pub double_click: u16,
+    /// Buttons which reached the long-press time
+    pub long_press: u16,
+    /// Buttons which auto-repeated
+    pub repeat: u16,
+}
+
+impl Gestures {
+    /// Order in which the gestures on one pin are reported, which is the
+    /// order they happened in
+    const ORDER: [Gesture; 6] = [
+        Gesture::Release,
+        Gesture::Click,
+        Gesture::DoubleClick,
+        Gesture::Press,
+        Gesture::LongPress,
+        Gesture::Repeat,
+    ];
+
+    /// Returns `true` if no gestures were recognised
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The pins on which a gesture was made
+    pub fn mask(&self, gesture: Gesture) -> u16 {
+        match gesture {
+            Gesture::Press => self.press,
+            Gesture::Release => self.release,
+            Gesture::Click => self.click,
+            Gesture::DoubleClick => self.double_click,
+            Gesture::LongPress => self.long_press,
+            Gesture::Repeat => self.repeat,
+        }
+    }
+
+    /// Iterate over the individual gestures, in order of pin index
+    pub fn events(&self) -> GestureEvents {
+        GestureEvents {
+            gestures: *self,
+            pin: 0,
+            next: 0,
+        }
+    }
+}
+
+/// Iterator over the gestures in a [`Gestures`], as returned by
+/// [`Gestures::events`]
+pub struct GestureEvents {
+    gestures: Gestures,
+    pin: u8,
+    next: usize,
+}
+
+impl Iterator for GestureEvents {
+    type Item = GestureEvent;
+
+    fn next(&mut self) -> Option<GestureEvent> {
+        while self.pin < 16 {
+            while let Some(&gesture) = Gestures::ORDER.get(self.next) {
+                self.next += 1;
+                if self.gestures.mask(gesture) & (1 << self.pin) != 0 {
+                    return Some(GestureEvent {
+                        pin: self.pin,
+                        gesture,
+                    });
+                }
+            }
+            self.pin += 1;
+            self.next = 0;
+        }
+        None
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Button {
+    Idle,
+    Pressed {
+        since: u64,
+        /// This is the second press of a possible double-click
+        second: bool,
+        long: bool,
+        next_repeat: u64,
+    },
+    /// Released after a short press, waiting to see if a second press
+    /// follows within the double-click window
+    Released {
+        at: u64,
+    },
+}
+
+/// Recognises gestures on each of the selected button pins
+#[derive(Copy, Clone, Debug)]
+pub struct GestureDetector {
+    pins: u16,
+    timings: Timings,
+    buttons: [Button; 16],
+}
+
+impl GestureDetector {
+    /// Create a detector for buttons on the pins selected by `pins`, which
+    /// are all assumed to be released
+    pub fn new(pins: u16, timings: Timings) -> Self {
+        Self {
+            pins,
+            timings,
+            buttons: [Button::Idle; 16],
+        }
+    }
+
+    /// The timings used to recognise gestures
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Record a new sample taken at time `now`, returning the gestures
+    /// which were completed. This should be called regularly even when the
+    /// inputs have not changed, as clicks, long presses and repeats are
+    /// recognised by time. Timestamps must not decrease between samples.
+    pub fn update(&mut self, sample: u16, now: u64) -> Gestures {
+        let Timings {
+            double_click,
+            long_press,
+            repeat_interval,
+        } = self.timings;
+        let mut gestures = Gestures::default();
+        for (pin, button) in self.buttons.iter_mut().enumerate() {
+            let mask = 1 << pin;
+            if self.pins & mask == 0 {
+                continue;
+            }
+            let down = sample & mask == 0;
+            let pressed = |second| Button::Pressed {
+                since: now,
+                second,
+                long: false,
+                next_repeat: 0,
+            };
+            *button = match (*button, down) {
+                (Button::Idle, false) => Button::Idle,
+                (Button::Idle, true) => {
+                    gestures.press |= mask;
+                    pressed(false)
+                }
+                (Button::Released { at }, down) => {
+                    let within = now.saturating_sub(at) < double_click;
+                    if !within {
+                        gestures.click |= mask;
+                    }
+                    if down {
+                        gestures.press |= mask;
+                        pressed(within)
+                    } else if within {
+                        Button::Released { at }
+                    } else {
+                        Button::Idle
+                    }
+                }
+                (Button::Pressed { long, second, .. }, false) => {
+                    gestures.release |= mask;
+                    if long {
+                        Button::Idle
+                    } else if second {
+                        gestures.double_click |= mask;
+                        Button::Idle
+                    } else if double_click == 0 {
+                        gestures.click |= mask;
+                        Button::Idle
+                    } else {
+                        Button::Released { at: now }
+                    }
+                }
+                (
+                    Button::Pressed {
+                        since,
+                        second,
+                        long: false,
+                        ..
+                    },
+                    true,
+                ) if now.saturating_sub(since) >= long_press => {
+                    // The first press of what was going to be a
+                    // double-click was a click on its own
+                    if second {
+                        gestures.click |= mask;
+                    }
+                    gestures.long_press |= mask;
+                    Button::Pressed {
+                        since,
+                        second: false,
+                        long: true,
+                        next_repeat: (since + long_press)
+                            .saturating_add(repeat_interval.unwrap_or(0)),
+                    }
+                }
+                (
+                    Button::Pressed {
+                        since,
+                        second,
+                        long: true,
+                        next_repeat,
+                    },
+                    true,
+                ) => match repeat_interval {
+                    Some(interval) if now >= next_repeat => {
+                        gestures.repeat |= mask;
+                        Button::Pressed {
+                            since,
+                            second,
+                            long: true,
+                            next_repeat: next_repeat.saturating_add(interval),
+                        }
+                    }
+                    _ => *button,
+                },
+                (Button::Pressed { .. }, true) => *button,
+            };
+        }
+        gestures
+    }
+
+    /// Read the inputs from the driver and record them as a new sample
+    /// taken at time `now`
+    pub fn sample<I2C: I2c, C: Chip>(
+        &mut self,
+        tca: &mut Tca9555<I2C, C>,
+        now: u64,
+    ) -> Result<Gestures, Error<I2C::Error>> {
+        Ok(self.update(tca.read_all()?, now))
+    }
+
+    /// Read the inputs from the async driver and record them as a new
+    /// sample taken at time `now`
+    #[cfg(feature = "async")]
+    pub async fn sample_async<I2C, C: Chip>(
+        &mut self,
+        tca: &mut crate::Tca9555Async<I2C, C>,
+        now: u64,
+    ) -> Result<Gestures, Error<I2C::Error>>
+    where
+        I2C: embedded_hal_async::i2c::I2c,
+    {
+        Ok(self.update(tca.read_all().await?, now))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn events(gestures: Gestures) -> Vec<(u8, Gesture)> {
+        gestures.events().map(|e| (e.pin, e.gesture)).collect()
+    }
+
+    #[test]
+    fn click_and_double_click() {
+        let mut detector = GestureDetector::new(0x0003, Timings::default());
+        assert_eq!(events(detector.update(0xfffe, 0)), [(0, Gesture::Press)]);
+        assert_eq!(
+            events(detector.update(0xffff, 100)),
+            [(0, Gesture::Release)]
+        );
+        assert!(detector.update(0xffff, 399).is_empty());
+        assert_eq!(events(detector.update(0xffff, 400)), [(0, Gesture::Click)]);
+        // Pin 1 clicks twice, while pin 15 is not a button
+        detector.update(0x7ffd, 1000);
+        detector.update(0xffff, 1050);
+        assert_eq!(
+            events(detector.update(0xfffd, 1200)),
+            [(1, Gesture::Press)]
+        );
+        assert_eq!(
+            events(detector.update(0xffff, 1250)),
+            [(1, Gesture::Release), (1, Gesture::DoubleClick)]
+        );
+        assert!(detector.update(0xffff, 2000).is_empty());
+    }
+
+    #[test]
+    fn click_then_hold() {
+        let mut detector = GestureDetector::new(0x0001, Timings::default());
+        detector.update(0xfffe, 0);
+        detector.update(0xffff, 100);
+        assert_eq!(events(detector.update(0xfffe, 200)), [(0, Gesture::Press)]);
+        assert!(detector.update(0xfffe, 999).is_empty());
+        assert_eq!(
+            events(detector.update(0xfffe, 1000)),
+            [(0, Gesture::Click), (0, Gesture::LongPress)]
+        );
+        assert_eq!(
+            events(detector.update(0xffff, 1100)),
+            [(0, Gesture::Release)]
+        );
+        assert!(detector.update(0xffff, 2000).is_empty());
+    }
+
+    #[test]
+    fn long_press_and_repeat() {
+        let timings = Timings {
+            double_click: 0,
+            long_press: 500,
+            repeat_interval: Some(100),
+        };
+        let mut detector = GestureDetector::new(0x0100, timings);
+        assert_eq!(events(detector.update(0xfeff, 0)), [(8, Gesture::Press)]);
+        assert_eq!(
+            events(detector.update(0xffff, 10)),
+            [(8, Gesture::Release), (8, Gesture::Click)]
+        );
+        detector.update(0xfeff, 1000);
+        assert!(detector.update(0xfeff, 1499).is_empty());
+        assert_eq!(detector.update(0xfeff, 1500).long_press, 0x0100);
+        assert!(detector.update(0xfeff, 1550).is_empty());
+        assert_eq!(detector.update(0xfeff, 1600).repeat, 0x0100);
+        assert_eq!(detector.update(0xfeff, 1700).repeat, 0x0100);
+        assert_eq!(
+            events(detector.update(0xffff, 1720)),
+            [(8, Gesture::Release)]
+        );
+    }
+}
@@ -0,0 +1,268 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Detection of chip resets, such as after a brown-out.
+//!
+//! A reset returns every register to its power-on value, turning all the
+//! outputs back into inputs without the driver being told. The health
+//! check reads the output, polarity inversion and configuration registers
+//! and compares them with the driver's register cache, and can write the
+//! cached state back to the chip if they differ. It is cheap enough to run
+//! periodically from a main loop.
+//!
+//! A reset can only be recognised once the cache differs from the
+//! power-on state, that is once the chip has been configured.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::health::{Health, Recovery};
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn watchdog<I2C: I2c>(
+//!     tca: &mut Tca9555<I2C>,
+//! ) -> Result<(), Error<I2C::Error>> {
+//!     if let Health::Reset { .. } = tca.check_health(Recovery::Restore)? {
+//!         // the outputs were restored, but log that the chip was reset
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::chip::Chip;
+use crate::{Config, Error, Registers, Tca9555};
+use embedded_hal::i2c::I2c;
+
+/// What to do when the chip's registers do not match the cache
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Recovery {
+    /// Only report the difference
+    Report,
+    /// Write the cached registers back to the chip with
+    /// [`Tca9555::apply`], starting from the registers which were read, so
+    /// pins which have wrongly become outputs are released before any
+    /// output level is changed
+    Restore,
+}
+
+/// Result of a health check
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum Health {
+    /// The registers match the cache
+    Ok,
+    /// The registers have returned to their power-on values, so the chip
+    /// has been reset
+    Reset {
+        /// The cached registers were written back to the chip
+        restored: bool,
+    },
+    /// The registers differ from the cache, but are not the power-on
+    /// values. This can happen if another bus master has written to the
+    /// chip, or the bus is unreliable.
+    Mismatch {
+        /// The registers read from the chip
+        actual: Registers,
+        /// The cached registers were written back to the chip
+        restored: bool,
+    },
+}
+
+impl Health {
+    /// Classify the registers read from the chip against the cache
+    fn classify(
+        expected: Registers,
+        actual: Registers,
+        restored: bool,
+    ) -> Self {
+        if actual == expected {
+            Self::Ok
+        } else if actual == Registers::POWER_ON {
+            Self::Reset { restored }
+        } else {
+            Self::Mismatch { actual, restored }
+        }
+    }
+}
+
+/// Define the health check of a driver, in the same way as the register
+/// access methods shared by both drivers
+macro_rules! health_methods {
+    ($driver:ident, $io:ident $(, $async:tt)?) => {
+        impl<I2C, E, C: Chip> $driver<I2C, C>
+        where
+            I2C: I2c<Error = E>,
+        {
+            /// Compare the chip's registers with the register cache,
+            /// optionally restoring the cached state if they differ
+            pub $($async)? fn check_health(
+                &mut self,
+                recovery: Recovery,
+            ) -> Result<Health, Error<E>> {
+                let actual = Registers {
+                    output: $io!(self.read_output_all())?,
+                    polarity_invert: $io!(self.read_polarity_invert_all())?,
+                    direction: $io!(self.read_direction_all())?,
+                };
+                let expected = self.registers();
+                let restore =
+                    actual != expected && recovery == Recovery::Restore;
+                if restore {
+                    self.registers = actual;
+                    let config = Config::from_registers(expected);
+                    if let Err(error) = $io!(self.apply(&config)) {
+                        // Keep the intended state, so the next check still
+                        // differs
+                        self.registers = expected;
+                        return Err(error);
+                    }
+                }
+                Ok(Health::classify(expected, actual, restore))
+            }
+        }
+    };
+}
+
+health_methods!(Tca9555, blocking);
+
+#[cfg(feature = "async")]
+mod asynch {
+    use super::*;
+    use crate::Tca9555Async;
+    use embedded_hal_async::i2c::I2c;
+
+    health_methods!(Tca9555Async, awaited, async);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::*;
+    use crate::DeviceAddr;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    #[test]
+    fn mismatch_reported() {
+        let expectations = [
+            Transaction::write_read(0x20, vec![WRITE_PORT_0], vec![0xff, 0xff]),
+            Transaction::write_read(
+                0x20,
+                vec![POLARITY_INVERT_PORT_0],
+                vec![0x00, 0x00],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![CONFIGURATION_PORT_0],
+                vec![0x0f, 0xff],
+            ),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        assert_eq!(
+            tca.check_health(Recovery::Report),
+            Ok(Health::Mismatch {
+                actual: Registers {
+                    direction: 0xff0f,
+                    ..Registers::POWER_ON
+                },
+                restored: false,
+            })
+        );
+        i2c.done();
+    }
+
+    #[test]
+    fn mismatch_restored() {
+        let expectations = [
+            // Another master has made pin 4 an output driving low
+            Transaction::write_read(0x20, vec![WRITE_PORT_0], vec![0xef, 0xff]),
+            Transaction::write_read(
+                0x20,
+                vec![POLARITY_INVERT_PORT_0],
+                vec![0x00, 0x00],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![CONFIGURATION_PORT_0],
+                vec![0xef, 0xff],
+            ),
+            // Pin 4 is released before its output level is restored
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xff]),
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0xff, 0xff]),
+            Transaction::write(0x20, vec![POLARITY_INVERT_PORT_0, 0x00, 0x00]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xff, 0xff]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        assert_eq!(
+            tca.check_health(Recovery::Restore),
+            Ok(Health::Mismatch {
+                actual: Registers {
+                    output: 0xffef,
+                    polarity_invert: 0x0000,
+                    direction: 0xffef,
+                },
+                restored: true,
+            })
+        );
+        assert_eq!(tca.registers(), Registers::POWER_ON);
+        i2c.done();
+    }
+
+    #[test]
+    fn failed_restore_keeps_cache() {
+        use embedded_hal::i2c::ErrorKind;
+        let read = [
+            Transaction::write_read(0x20, vec![WRITE_PORT_0], vec![0xef, 0xff]),
+            Transaction::write_read(
+                0x20,
+                vec![POLARITY_INVERT_PORT_0],
+                vec![0x00, 0x00],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![CONFIGURATION_PORT_0],
+                vec![0xef, 0xff],
+            ),
+        ];
+        let mut expectations = read.to_vec();
+        expectations.push(
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xff])
+                .with_error(ErrorKind::Other),
+        );
+        expectations.extend(read);
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        assert_eq!(
+            tca.check_health(Recovery::Restore),
+            Err(Error::Bus(ErrorKind::Other))
+        );
+        assert_eq!(tca.registers(), Registers::POWER_ON);
+        assert!(matches!(
+            tca.check_health(Recovery::Report),
+            Ok(Health::Mismatch { .. })
+        ));
+        i2c.done();
+    }
+
+    #[cfg(feature = "sim")]
+    #[test]
+    fn reset_restored() {
+        use crate::sim::Tca9555Sim;
+        let sim = Tca9555Sim::new(DeviceAddr::default());
+        let mut tca = Tca9555::new(sim.i2c(), DeviceAddr::default());
+        tca.enable_outputs(0x00ff, 0x0055).unwrap();
+        assert_eq!(tca.check_health(Recovery::Restore), Ok(Health::Ok));
+        sim.reset();
+        assert_eq!(
+            tca.check_health(Recovery::Report),
+            Ok(Health::Reset { restored: false })
+        );
+        assert_eq!(
+            tca.check_health(Recovery::Restore),
+            Ok(Health::Reset { restored: true })
+        );
+        assert_eq!(sim.registers(), tca.registers());
+        assert_eq!(sim.pin_levels(), 0xff55);
+    }
+}
@@ -0,0 +1,152 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Input change detection using the open-drain INT output.
+//!
+//! The TCA9555 pulls INT low whenever an input pin differs from the value
+//! captured at the last read of its input port register. Reading the port
+//! which triggered the interrupt clears it, so the methods here always read
+//! both ports in a single transaction and report the [`Changes`] since the
+//! previous read.
+//!
+//! ```no_run
+//! use embedded_hal::digital::InputPin;
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn poll<I2C: I2c>(
+//!     i2c: I2C,
+//!     mut int: impl InputPin,
+//! ) -> Result<(), Error<I2C::Error>> {
+//!     let mut tca = Tca9555::new(i2c, DeviceAddr::default());
+//!     loop {
+//!         if let Some(changes) = tca.poll_interrupt(&mut int)? {
+//!             for event in changes.edges() {
+//!                 // handle the edge on `event.pin`
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::chip::Chip;
+use crate::{Changes, Error, Tca9555};
+use embedded_hal::digital::InputPin;
+use embedded_hal::i2c::I2c;
+
+impl<I2C, E, C: Chip> Tca9555<I2C, C>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Read both input ports and return the changes since the previous
+    /// read. This also clears any pending interrupt.
+    pub fn read_changes(&mut self) -> Result<Changes, Error<E>> {
+        let previous = self.last_inputs();
+        Ok(Changes::between(previous, self.read_all()?))
+    }
+
+    /// Check the INT pin, which is active low. If an interrupt is pending
+    /// the inputs are read, clearing it, and the changes since the previous
+    /// read are returned; otherwise no I2C transaction is performed.
+    pub fn poll_interrupt<P: InputPin>(
+        &mut self,
+        int: &mut P,
+    ) -> Result<Option<Changes>, Error<E>> {
+        if int.is_high().map_err(Error::int_pin)? {
+            return Ok(None);
+        }
+        self.read_changes().map(Some)
+    }
+}
+
+#[cfg(feature = "async")]
+mod asynch {
+    use super::*;
+    use crate::Tca9555Async;
+    use embedded_hal_async::digital::Wait;
+    use embedded_hal_async::i2c::I2c;
+
+    impl<I2C, E, C: Chip> Tca9555Async<I2C, C>
+    where
+        I2C: I2c<Error = E>,
+    {
+        /// Read both input ports and return the changes since the previous
+        /// read. This also clears any pending interrupt.
+        pub async fn read_changes(&mut self) -> Result<Changes, Error<E>> {
+            let previous = self.last_inputs();
+            Ok(Changes::between(previous, self.read_all().await?))
+        }
+
+        /// Wait for the INT pin to go low, then read the inputs and return
+        /// the changes since the previous read. If the inputs have returned
+        /// to their previous levels by the time they are read then this
+        /// continues waiting, so the returned changes are never empty.
+        pub async fn wait_for_change<P: Wait>(
+            &mut self,
+            int: &mut P,
+        ) -> Result<Changes, Error<E>> {
+            loop {
+                int.wait_for_low().await.map_err(Error::int_pin)?;
+                let changes = self.read_changes().await?;
+                if !changes.is_empty() {
+                    return Ok(changes);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::*;
+    use crate::DeviceAddr;
+    use embedded_hal_mock::eh1::digital::{
+        Mock as PinMock, State, Transaction as PinTransaction,
+    };
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    #[test]
+    fn poll_reports_changes() {
+        let expectations = [
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xfe, 0xff]),
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xff, 0x7f]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut int = PinMock::new(&[
+            PinTransaction::get(State::High),
+            PinTransaction::get(State::Low),
+            PinTransaction::get(State::Low),
+        ]);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        assert_eq!(tca.poll_interrupt(&mut int).unwrap(), None);
+        let changes = tca.poll_interrupt(&mut int).unwrap().unwrap();
+        assert_eq!(changes.falling, 0x0001);
+        let changes = tca.poll_interrupt(&mut int).unwrap().unwrap();
+        assert_eq!((changes.rising, changes.falling), (0x0001, 0x8000));
+        assert_eq!(tca.last_inputs(), 0x7fff);
+        i2c.done();
+        int.done();
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn wait_skips_spurious_interrupts() {
+        use crate::Tca9555Async;
+        let expectations = [
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xff, 0xff]),
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xff, 0xfd]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut int = PinMock::new(&[
+            PinTransaction::wait_for_state(State::Low),
+            PinTransaction::wait_for_state(State::Low),
+        ]);
+        let mut tca = Tca9555Async::new(i2c.clone(), DeviceAddr::default());
+        let changes =
+            pollster::block_on(tca.wait_for_change(&mut int)).unwrap();
+        assert_eq!(changes.falling, 0x0200);
+        i2c.done();
+        int.done();
+    }
+}
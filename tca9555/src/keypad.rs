@@ -0,0 +1,403 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Keypad scanning, for keys wired directly to the pins or in a matrix.
+//!
+//! Keys are active low: they connect a column pin to ground, either
+//! directly or through a row pin which is driven low while that row is
+//! scanned. The column pins need pull-ups, fitted externally on chips
+//! without internal pull-ups such as the TCA9535 and TCA9539, and must
+//! not have their polarity inverted. Rows which are not being scanned are
+//! left as inputs, so pressing several keys never shorts two driven rows
+//! together.
+//!
+//! Keys are numbered `row * COLS + column`. A change is only reported once
+//! the scan has read the same for the configured number of consecutive
+//! scans, and scans in which a matrix without diodes is ghosting are
+//! ignored.
+//!
+//! ```no_run
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::keypad::{KeyEvent, Keypad};
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn keys<I2C: I2c>(i2c: I2C) -> Result<(), Error<I2C::Error>> {
+//!     let mut tca = Tca9555::new(i2c, DeviceAddr::default());
+//!     // Rows on pins 0-3, columns on pins 8-11
+//!     let mut keypad = Keypad::matrix([0, 1, 2, 3], [8, 9, 10, 11]);
+//!     keypad.init(&mut tca)?;
+//!     loop {
+//!         for event in keypad.scan(&mut tca)? {
+//!             if let KeyEvent::Down(key) = event {
+//!                 let (row, column) = keypad.position(key);
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::chip::Chip;
+use crate::{Error, Tca9555};
+use embedded_hal::i2c::I2c;
+
+/// A key changing state
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub enum KeyEvent {
+    /// The key with this number was pressed
+    Down(u8),
+    /// The key with this number was released
+    Up(u8),
+}
+
+/// Iterator over the key events from a scan, in order of key number, as
+/// returned by [`Keypad::scan`]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct KeyEvents {
+    remaining: u64,
+    pressed: u64,
+}
+
+impl KeyEvents {
+    /// Returns `true` if no keys changed state
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+impl Iterator for KeyEvents {
+    type Item = KeyEvent;
+
+    fn next(&mut self) -> Option<KeyEvent> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let key = self.remaining.trailing_zeros() as u8;
+        let mask = 1 << key;
+        self.remaining &= !mask;
+        if self.pressed & mask != 0 {
+            Some(KeyEvent::Down(key))
+        } else {
+            Some(KeyEvent::Up(key))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.remaining.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for KeyEvents {}
+
+/// Keypad of `ROWS` by `COLS` keys. Bit `n` of a key state corresponds to
+/// key `n`.
+#[derive(Copy, Clone, Debug)]
+pub struct Keypad<const ROWS: usize, const COLS: usize> {
+    /// Pins driving each row, or `None` if the keys are wired directly to
+    /// the column pins
+    rows: Option<[u8; ROWS]>,
+    cols: [u8; COLS],
+    debounce_scans: u8,
+    candidate: u64,
+    count: u8,
+    pressed: u64,
+    ghosting: bool,
+}
+
+impl<const COLS: usize> Keypad<1, COLS> {
+    /// Create a keypad where each key connects one pin to ground, with key
+    /// `n` on pin `pins[n]`
+    ///
+    /// # Panics
+    /// If any pin is out of range or used more than once
+    pub fn direct(pins: [u8; COLS]) -> Self {
+        Self::new(None, pins)
+    }
+}
+
+impl Keypad<1, 16> {
+    /// Pimoroni PIM551 RGB keypad, where the 16 keys are wired directly to
+    /// the pins with key `n` on pin `n`
+    pub fn pim551() -> Self {
+        Self::direct(core::array::from_fn(|pin| pin as u8))
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Keypad<ROWS, COLS> {
+    /// Number of consecutive identical scans required before a change is
+    /// reported, unless changed with
+    /// [`with_debounce`](Self::with_debounce)
+    pub const DEFAULT_DEBOUNCE_SCANS: u8 = 3;
+
+    const VALID_SIZE: () = assert!(
+        ROWS >= 1 && COLS >= 1 && COLS <= 16 && ROWS * COLS <= 64,
+        "a keypad has between 1 and 64 keys, with at most 16 columns"
+    );
+
+    /// Create a keypad with rows driven by the `rows` pins and columns read
+    /// from the `cols` pins. Rows without diodes are supported, with
+    /// ghosting detected and ignored.
+    ///
+    /// # Panics
+    /// If any pin is out of range or used more than once
+    pub fn matrix(rows: [u8; ROWS], cols: [u8; COLS]) -> Self {
+        Self::new(Some(rows), cols)
+    }
+
+    fn new(rows: Option<[u8; ROWS]>, cols: [u8; COLS]) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_SIZE;
+        let mut used = 0u16;
+        for &pin in rows.iter().flatten().chain(&cols) {
+            assert!(pin < 16, "pin {} is out of range", pin);
+            assert!(used & (1 << pin) == 0, "pin {} is used twice", pin);
+            used |= 1 << pin;
+        }
+        Self {
+            rows,
+            cols,
+            debounce_scans: Self::DEFAULT_DEBOUNCE_SCANS,
+            candidate: 0,
+            count: 0,
+            pressed: 0,
+            ghosting: false,
+        }
+    }
+
+    /// Set the number of consecutive identical scans required before a
+    /// change is reported. A value of 1 disables debouncing.
+    pub fn with_debounce(mut self, scans: u8) -> Self {
+        self.debounce_scans = scans.max(1);
+        self
+    }
+
+    /// Row and column of a key
+    pub fn position(&self, key: u8) -> (u8, u8) {
+        let key = usize::from(key);
+        ((key / COLS) as u8, (key % COLS) as u8)
+    }
+
+    /// Debounced state of every key, where a set bit is a pressed key
+    pub fn pressed(&self) -> u64 {
+        self.pressed
+    }
+
+    /// Returns `true` if the key is currently pressed
+    pub fn is_pressed(&self, key: u8) -> bool {
+        key < 64 && self.pressed & (1 << key) != 0
+    }
+
+    /// Returns `true` if the most recent scan was ignored because of
+    /// ghosting
+    pub fn is_ghosting(&self) -> bool {
+        self.ghosting
+    }
+
+    fn row_mask(&self) -> u16 {
+        self.rows
+            .iter()
+            .flatten()
+            .fold(0, |mask, pin| mask | (1 << pin))
+    }
+
+    fn col_mask(&self) -> u16 {
+        self.cols.iter().fold(0, |mask, pin| mask | (1 << pin))
+    }
+
+    /// Column states of one row from an input sample, where a set bit is a
+    /// column pulled low
+    fn columns(&self, inputs: u16) -> u64 {
+        self.cols
+            .iter()
+            .enumerate()
+            .fold(0, |columns, (col, &pin)| {
+                if inputs & (1 << pin) == 0 {
+                    columns | (1 << col)
+                } else {
+                    columns
+                }
+            })
+    }
+
+    /// Returns `true` if two rows share two or more pressed columns, in
+    /// which case a fourth key at the corner of the rectangle they form
+    /// could be a phantom
+    fn is_ghost(&self, raw: u64) -> bool {
+        let row = |index: usize| (raw >> (index * COLS)) & ((1 << COLS) - 1);
+        (0..ROWS).any(|first| {
+            (first + 1..ROWS)
+                .any(|second| (row(first) & row(second)).count_ones() >= 2)
+        })
+    }
+
+    /// Record a raw scan, where a set bit is a key which reads as pressed,
+    /// returning the debounced changes. This is called by
+    /// [`scan`](Self::scan) and can be used with another source of scans.
+    pub fn update(&mut self, raw: u64) -> KeyEvents {
+        self.ghosting = self.is_ghost(raw);
+        if self.ghosting {
+            return KeyEvents::default();
+        }
+        if raw != self.candidate {
+            self.candidate = raw;
+            self.count = 0;
+        }
+        self.count = self.count.saturating_add(1);
+        if self.count < self.debounce_scans {
+            return KeyEvents::default();
+        }
+        let changed = raw ^ self.pressed;
+        self.pressed = raw;
+        KeyEvents {
+            remaining: changed,
+            pressed: raw,
+        }
+    }
+
+    /// Configure the row and column pins as inputs, with the output
+    /// register of the row pins preloaded low ready for scanning
+    pub fn init<I2C: I2c, C: Chip>(
+        &self,
+        tca: &mut Tca9555<I2C, C>,
+    ) -> Result<(), Error<I2C::Error>> {
+        let rows = self.row_mask();
+        if rows != 0 {
+            tca.modify_outputs(rows, 0x0000)?;
+        }
+        tca.modify_directions(rows | self.col_mask(), 0xffff)
+    }
+
+    /// Scan every key, returning the debounced changes. [`init`](Self::init)
+    /// must have been called first.
+    pub fn scan<I2C: I2c, C: Chip>(
+        &mut self,
+        tca: &mut Tca9555<I2C, C>,
+    ) -> Result<KeyEvents, Error<I2C::Error>> {
+        let Some(rows) = self.rows else {
+            let raw = self.columns(tca.read_all()?);
+            return Ok(self.update(raw));
+        };
+        let mut raw = 0;
+        for (index, pin) in rows.iter().enumerate() {
+            tca.modify_directions(self.row_mask(), !(1 << pin))?;
+            raw |= self.columns(tca.read_all()?) << (index * COLS);
+        }
+        tca.modify_directions(self.row_mask(), 0xffff)?;
+        Ok(self.update(raw))
+    }
+
+    /// Scan every key using the async driver, returning the debounced
+    /// changes. [`init_async`](Self::init_async) must have been called
+    /// first.
+    #[cfg(feature = "async")]
+    pub async fn scan_async<I2C, C: Chip>(
+        &mut self,
+        tca: &mut crate::Tca9555Async<I2C, C>,
+    ) -> Result<KeyEvents, Error<I2C::Error>>
+    where
+        I2C: embedded_hal_async::i2c::I2c,
+    {
+        let Some(rows) = self.rows else {
+            let raw = self.columns(tca.read_all().await?);
+            return Ok(self.update(raw));
+        };
+        let mut raw = 0;
+        for (index, pin) in rows.iter().enumerate() {
+            tca.modify_directions(self.row_mask(), !(1 << pin)).await?;
+            raw |= self.columns(tca.read_all().await?) << (index * COLS);
+        }
+        tca.modify_directions(self.row_mask(), 0xffff).await?;
+        Ok(self.update(raw))
+    }
+
+    /// Configure the row and column pins using the async driver, as for
+    /// [`init`](Self::init)
+    #[cfg(feature = "async")]
+    pub async fn init_async<I2C, C: Chip>(
+        &self,
+        tca: &mut crate::Tca9555Async<I2C, C>,
+    ) -> Result<(), Error<I2C::Error>>
+    where
+        I2C: embedded_hal_async::i2c::I2c,
+    {
+        let rows = self.row_mask();
+        if rows != 0 {
+            tca.modify_outputs(rows, 0x0000).await?;
+        }
+        tca.modify_directions(rows | self.col_mask(), 0xffff).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::*;
+    use crate::DeviceAddr;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    #[test]
+    fn matrix_scan() {
+        let expectations = [
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0xfc]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xff, 0xff]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xfe]),
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xfe, 0xfd]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xfd]),
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xfd, 0xff]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xff]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        let mut keypad = Keypad::matrix([0, 1], [8, 9]).with_debounce(1);
+        keypad.init(&mut tca).unwrap();
+        let events = keypad.scan(&mut tca).unwrap();
+        assert_eq!(events.collect::<Vec<_>>(), [KeyEvent::Down(1)]);
+        assert_eq!(keypad.position(1), (0, 1));
+        assert!(keypad.is_pressed(1));
+        i2c.done();
+    }
+
+    #[test]
+    fn debounce_and_ghosting() {
+        let mut keypad = Keypad::matrix([0, 1, 2], [8, 9, 10]);
+        assert!(keypad.update(0b000_000_001).is_empty());
+        assert!(keypad.update(0b000_000_001).is_empty());
+        let events = keypad.update(0b000_000_001);
+        assert_eq!(events.collect::<Vec<_>>(), [KeyEvent::Down(0)]);
+        // A bounce restarts the count
+        keypad.update(0b000_000_000);
+        keypad.update(0b000_000_001);
+        keypad.update(0b000_000_000);
+        keypad.update(0b000_000_000);
+        let events = keypad.update(0b000_000_000);
+        assert_eq!(events.collect::<Vec<_>>(), [KeyEvent::Up(0)]);
+        // Keys 0, 1 and 3 pressed would also show key 4
+        assert!(keypad.update(0b000_011_011).is_empty());
+        assert!(keypad.is_ghosting());
+        assert_eq!(keypad.pressed(), 0);
+        keypad.update(0b100_000_010);
+        assert!(!keypad.is_ghosting());
+    }
+
+    #[test]
+    fn pim551_direct() {
+        let expectations = [
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0xff, 0xff]),
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xff, 0x7e]),
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xff, 0xfe]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        let mut keypad = Keypad::pim551().with_debounce(1);
+        keypad.init(&mut tca).unwrap();
+        let events = keypad.scan(&mut tca).unwrap();
+        assert_eq!(
+            events.collect::<Vec<_>>(),
+            [KeyEvent::Down(8), KeyEvent::Down(15)]
+        );
+        let events = keypad.scan(&mut tca).unwrap();
+        assert_eq!(events.collect::<Vec<_>>(), [KeyEvent::Up(15)]);
+        i2c.done();
+    }
+}
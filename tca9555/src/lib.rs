@@ -7,14 +7,28 @@
 
 //! Driver for the TCA9555/TCA9535 16-port I/O expander.
 //!
-//! This crate currently only has byte-oriented operation implemented.
-//! Individual control of pins will be the subject of a future release.
+//! Other chips in the family, including the PCA9555, TCA9539, XL9555 and
+//! the 8-bit PCA9554 and TCA9554, are supported through the markers in
+//! the [`chip`] module.
+//!
+//! Ports can be read and written a byte at a time, or the expander can be
+//! split into 16 individual pins which implement the embedded-hal digital
+//! traits (see [`Tca9555::split`]).
+//!
+//! The driver is built on the embedded-hal 1.0 [`I2c`] trait. Buses which
+//! only implement the embedded-hal 0.2 traits can be used through the
+//! adapter in the `compat` module, available with the `eh02` feature. An
+//! async driver built on embedded-hal-async is available in the `asynch`
+//! module with the `async` feature.
+//!
+//! The `sim` feature adds a software model of the chip in the `sim`
+//! module, for testing code which uses the driver on the host.
 //!
 //! ## Example
 //! ```no_run
-//! use embedded_hal::blocking::i2c::WriteRead;
-//! use tca9555::{Tca9555, DeviceAddr};
-//! fn read_ports<E>(i2c: impl WriteRead<Error = E>) -> Result<(), E> {
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn read_ports<E>(i2c: impl I2c<Error = E>) -> Result<(), Error<E>> {
 //!     let mut tca = Tca9555::new(i2c, DeviceAddr::default());
 //!     let port0: u8 = tca.read_port_0()?;
 //!     let port1: u8 = tca.read_port_1()?;
@@ -22,10 +36,86 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! ## Sharing the bus
+//! The driver takes ownership of its bus, which can be recovered with
+//! [`Tca9555::release`]. To use the expander alongside other devices on
+//! the same I2C lines, pass it one of the shared bus devices from the
+//! `embedded-hal-bus` crate: `RefCellDevice` within a single context,
+//! `CriticalSectionDevice` when the bus is also used from interrupts, or
+//! `AtomicDevice` which returns an error instead of blocking if the bus is
+//! already in use. Several chips on one bus can also be managed together
+//! with [`Tca9555Bank`].
+//!
+//! ```no_run
+//! use core::cell::RefCell;
+//! use embedded_hal::i2c::I2c;
+//! use embedded_hal_bus::i2c::RefCellDevice;
+//! use tca9555::{DeviceAddr, Error, Tca9555};
+//! fn shared<I2C: I2c>(i2c: I2C) -> Result<I2C, Error<I2C::Error>> {
+//!     let bus = RefCell::new(i2c);
+//!     let device = RefCellDevice::new(&bus);
+//!     let mut tca = Tca9555::new(device, DeviceAddr::default());
+//!     let mut sensor = RefCellDevice::new(&bus);
+//!     let inputs = tca.read_all()?;
+//!     sensor.write(0x48, &[0x01]).map_err(Error::Bus)?;
+//!     Ok(bus.into_inner())
+//! }
+//! ```
+
+use chip::{Chip, OnePort, TwoPorts};
+use core::cell::RefCell;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::str::FromStr;
+use embedded_hal::i2c::I2c;
+
+/// Log a register transaction when the `use_defmt` feature is enabled
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "use_defmt")]
+        defmt::trace!($($arg)*);
+    };
+}
+
+#[macro_use]
+mod driver;
+
+pub mod agile;
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod bank;
+pub mod changes;
+pub mod chip;
+#[cfg(feature = "eh02")]
+pub mod compat;
+pub mod config;
+pub mod debounce;
+pub mod discover;
+pub mod encoder;
+mod error;
+pub mod gesture;
+pub mod health;
+pub mod interrupt;
+pub mod keypad;
+pub mod pins;
+mod registers;
+pub mod reset;
+#[cfg(feature = "sim")]
+pub mod sim;
 
-use embedded_hal::blocking::i2c::{Write, WriteRead};
+#[cfg(feature = "async")]
+pub use asynch::Tca9555Async;
+pub use bank::Tca9555Bank;
+pub use changes::{Changes, InputTracker};
+pub use config::Config;
+pub use error::{AddrError, Error};
+pub use pins::{Parts, Parts8, Pin};
+pub use registers::Registers;
+use registers::{pin_mask, replace_port, RegisterWrite};
 
-mod command {
+pub(crate) mod command {
     pub const READ_PORT_0: u8 = 0x00;
     pub const READ_PORT_1: u8 = 0x01;
     pub const WRITE_PORT_0: u8 = 0x02;
@@ -34,31 +124,99 @@ mod command {
     pub const POLARITY_INVERT_PORT_1: u8 = 0x05;
     pub const CONFIGURATION_PORT_0: u8 = 0x06;
     pub const CONFIGURATION_PORT_1: u8 = 0x07;
+
+    // Agile I/O registers of the PCAL9555A and PCAL6416A
+    pub const OUTPUT_DRIVE_STRENGTH_0: u8 = 0x40;
+    pub const INPUT_LATCH_PORT_0: u8 = 0x44;
+    pub const PULL_ENABLE_PORT_0: u8 = 0x46;
+    pub const PULL_SELECT_PORT_0: u8 = 0x48;
+    pub const INTERRUPT_MASK_PORT_0: u8 = 0x4a;
+    pub const INTERRUPT_STATUS_PORT_0: u8 = 0x4c;
+    pub const OUTPUT_PORT_CONFIGURATION: u8 = 0x4f;
 }
 
 use command::*;
 
 /// Represents the address of a connected TCA9555
-#[derive(Copy, Clone, Debug)]
+///
+/// Addresses compare equal when they select the same address pins, so
+/// `Default` equals `Alternative(false, false, false)`. An address can be
+/// parsed from the raw TCA9555 address, such as `"0x23"`, or from the
+/// levels of the address pins, such as `"A0=1,A1=1"`, where pins which are
+/// not listed are low. It is displayed in the second form, which applies
+/// to every chip in the family. Raw addresses of the other chips are
+/// converted with [`DeviceAddr::from_address`].
+///
+/// ```
+/// use tca9555::DeviceAddr;
+/// let address: DeviceAddr = "0x23".parse().unwrap();
+/// assert_eq!(address, "A0=1,A1=1".parse().unwrap());
+/// assert_eq!(address, DeviceAddr::new(true, true, false));
+/// assert_eq!(address.to_string(), "A0=1,A1=1,A2=0");
+/// assert_eq!(DeviceAddr::try_from(0x23), Ok(address));
+/// let tca9539 = DeviceAddr::from_address::<tca9555::chip::Tca9539>(0x77);
+/// assert_eq!(tca9539, Ok(address));
+/// ```
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
 pub enum DeviceAddr {
-    /// Default address when all address pins are connected to GND (0x20)
+    /// Default address when all address pins are connected to GND (0x20
+    /// for a TCA9555)
+    #[default]
     Default,
     /// Set an alternative address with the values of the (A0, A1, A2)
     /// pins
     Alternative(bool, bool, bool),
 }
 
-impl Default for DeviceAddr {
-    fn default() -> Self {
-        Self::Default
-    }
-}
-
 impl DeviceAddr {
     const DEFAULT: u8 = 0x20;
 
-    /// Get the raw address
-    pub fn addr(self) -> u8 {
+    /// Create an address from the values of the A0, A1 and A2 pins
+    pub const fn new(a0: bool, a1: bool, a2: bool) -> Self {
+        Self::Alternative(a0, a1, a2)
+    }
+
+    /// Create an address from the values of the address pins, with A0 in
+    /// bit 0, returning `None` if a bit above A2 is set
+    pub const fn from_pins(pins: u8) -> Option<Self> {
+        if pins > 0b111 {
+            None
+        } else {
+            Some(Self::with_pins(pins))
+        }
+    }
+
+    /// The address with the pins in the low three bits of `pins`
+    const fn with_pins(pins: u8) -> Self {
+        match pins & 0b111 {
+            0 => Self::Default,
+            _ => Self::new(pins & 1 != 0, pins & 2 != 0, pins & 4 != 0),
+        }
+    }
+
+    /// Convert a raw address of the chip `C`, such as 0x74-0x77 for a
+    /// TCA9539
+    ///
+    /// # Errors
+    /// Returns [`AddrError::OutOfRange`] if `address` is not one which the
+    /// chip can be strapped to
+    pub fn from_address<C: Chip>(address: u8) -> Result<Self, AddrError> {
+        address
+            .checked_sub(C::BASE_ADDRESS)
+            .filter(|pins| pins >> C::ADDRESS_PINS == 0)
+            .map(Self::with_pins)
+            .ok_or(AddrError::OutOfRange(address))
+    }
+
+    /// Get the values of the address pins, with A0 in bit 0
+    pub const fn pins(self) -> u8 {
+        self.addr() - Self::DEFAULT
+    }
+
+    /// Get the raw address of a TCA9555. Other chips in the family add the
+    /// same offset to their own base address.
+    pub const fn addr(self) -> u8 {
         match self {
             DeviceAddr::Default => Self::DEFAULT,
             DeviceAddr::Alternative(a0, a1, a2) => {
@@ -66,6 +224,89 @@ impl DeviceAddr {
             }
         }
     }
+
+    /// Iterate over all eight addresses, in ascending order
+    pub fn all() -> impl ExactSizeIterator<Item = Self> + Clone {
+        (0..8).map(Self::with_pins)
+    }
+}
+
+impl PartialEq for DeviceAddr {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr() == other.addr()
+    }
+}
+
+impl Eq for DeviceAddr {}
+
+impl Hash for DeviceAddr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.addr().hash(state);
+    }
+}
+
+impl TryFrom<u8> for DeviceAddr {
+    type Error = AddrError;
+
+    /// Convert a raw TCA9555 address in the range 0x20-0x27. Use
+    /// [`DeviceAddr::from_address`] for the other chips in the family.
+    fn try_from(address: u8) -> Result<Self, AddrError> {
+        Self::from_address::<chip::Tca9555>(address)
+    }
+}
+
+impl fmt::Display for DeviceAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pins = self.pins();
+        write!(
+            f,
+            "A0={},A1={},A2={}",
+            pins & 1,
+            (pins >> 1) & 1,
+            (pins >> 2) & 1
+        )
+    }
+}
+
+impl FromStr for DeviceAddr {
+    type Err = AddrError;
+
+    fn from_str(s: &str) -> Result<Self, AddrError> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+            // from_str_radix also accepts a sign
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(AddrError::Syntax);
+            }
+            let address =
+                u8::from_str_radix(hex, 16).map_err(|_| AddrError::Syntax)?;
+            return Self::try_from(address);
+        }
+        let mut pins = 0;
+        let mut seen = 0;
+        for assignment in s.split(',') {
+            let (name, level) =
+                assignment.split_once('=').ok_or(AddrError::Syntax)?;
+            let name = name.trim();
+            let pin = match name.strip_prefix(['A', 'a']) {
+                Some("0") => 0,
+                Some("1") => 1,
+                Some("2") => 2,
+                _ => return Err(AddrError::Syntax),
+            };
+            let level: u8 = match level.trim() {
+                "0" => 0,
+                "1" => 1,
+                _ => return Err(AddrError::Syntax),
+            };
+            if seen & (1 << pin) != 0 {
+                return Err(AddrError::Syntax);
+            }
+            seen |= 1 << pin;
+            pins |= level << pin;
+        }
+        Ok(Self::with_pins(pins))
+    }
 }
 
 /// Type alias for TCA9555. Both chips implement the same I2C commands
@@ -73,115 +314,150 @@ impl DeviceAddr {
 /// been tested.
 pub type Tca9535<I2C> = Tca9555<I2C>;
 
-/// TCA9555 device
-pub struct Tca9555<I2C> {
+/// Driver for a PCA9555
+pub type Pca9555<I2C> = Tca9555<I2C, chip::Pca9555>;
+
+/// Driver for a TCA9539
+pub type Tca9539<I2C> = Tca9555<I2C, chip::Tca9539>;
+
+/// Driver for an XL9555
+pub type Xl9555<I2C> = Tca9555<I2C, chip::Xl9555>;
+
+/// Driver for a PCAL9555A
+pub type Pcal9555a<I2C> = Tca9555<I2C, chip::Pcal9555a>;
+
+/// Driver for a PCAL6416A
+pub type Pcal6416a<I2C> = Tca9555<I2C, chip::Pcal6416a>;
+
+/// Driver for an 8-bit PCA9554
+pub type Pca9554<I2C> = Tca9555<I2C, chip::Pca9554>;
+
+/// Driver for an 8-bit TCA9554
+pub type Tca9554<I2C> = Tca9555<I2C, chip::Tca9554>;
+
+/// TCA9555 device, or another chip in the family selected by the
+/// [`chip`] marker `C`
+pub struct Tca9555<I2C, C = chip::Tca9555> {
     address: DeviceAddr,
     i2c: I2C,
+    registers: Registers,
+    inputs: u16,
+    safe_state: Config,
+    chip: PhantomData<C>,
 }
 
 impl<I2C> Tca9555<I2C> {
-    /// Create a TCA9555 device with the given address
+    /// Create a TCA9555 device with the given address. The register cache
+    /// is assumed to hold the power-on defaults; use
+    /// [`refresh_registers`](Self::refresh_registers) if the chip may
+    /// already have been configured.
     pub fn new(i2c: I2C, address: DeviceAddr) -> Self {
-        Self { i2c, address }
+        Self::with_address(i2c, address)
     }
 }
 
-impl<I2C, E> Tca9555<I2C>
-where
-    I2C: WriteRead<Error = E>,
-{
-    /// Read input port 0 in full, returning a u8. Reads the current logic
-    /// level of the pins, regardless of whether they have been configured
-    /// as inputs or outputs
-    pub fn read_port_0(&mut self) -> Result<u8, E> {
-        let mut value = [0];
-        self.i2c
-            .write_read(self.address.addr(), &[READ_PORT_0], &mut value)
-            .and(Ok(value[0]))
+impl<I2C, C: Chip> Tca9555<I2C, C> {
+    /// Create a driver for a chip in the family other than the TCA9555,
+    /// such as `Pca9554::for_chip(i2c, address)`. The register cache is
+    /// assumed to hold the power-on defaults.
+    ///
+    /// # Errors
+    /// Returns [`AddrError::UnsupportedPins`] if `address` uses an address
+    /// pin which the chip does not have, such as A2 on a TCA9539
+    pub fn for_chip(i2c: I2C, address: DeviceAddr) -> Result<Self, AddrError> {
+        chip::check_address::<C>(address)?;
+        Ok(Self::with_address(i2c, address))
     }
 
-    /// Read input port 1 in full, returning a u8. Reads the current logic
-    /// level of the pins, regardless of whether they have been configured
-    /// as inputs or outputs
-    pub fn read_port_1(&mut self) -> Result<u8, E> {
-        let mut value = [0];
-        self.i2c
-            .write_read(self.address.addr(), &[READ_PORT_1], &mut value)
-            .and(Ok(value[0]))
+    /// Create a driver for an address which is known to suit the chip
+    fn with_address(i2c: I2C, address: DeviceAddr) -> Self {
+        Self {
+            i2c,
+            address,
+            registers: Registers::POWER_ON,
+            inputs: 0xffff,
+            safe_state: Config::new(),
+            chip: PhantomData,
+        }
     }
 
-    /// Read both input ports in turn, combining their values into a u16.
-    /// Reads the current logic level of the pins, regardless of whether
-    /// they have been configured as inputs or outputs
-    pub fn read_all(&mut self) -> Result<u16, E> {
-        let port0 = self.read_port_0()?;
-        let port1 = self.read_port_1()?;
-        Ok(u16::from_be_bytes([port1, port0]))
+    /// Get the cached copy of the output, polarity inversion and
+    /// configuration registers
+    pub fn registers(&self) -> Registers {
+        self.registers
     }
-}
 
-impl<I2C, E> Tca9555<I2C>
-where
-    I2C: Write<Error = E>,
-{
-    /// Write the given byte to port 0. Has no effect on pins which have
-    /// been configured as inputs.
-    pub fn write_port_0(&mut self, value: u8) -> Result<(), E> {
-        self.i2c.write(self.address.addr(), &[WRITE_PORT_0, value])
+    /// Get the input levels from the most recent read of the input ports.
+    /// All inputs are assumed to be high before the first read.
+    pub fn last_inputs(&self) -> u16 {
+        self.inputs
     }
 
-    /// Set the port 0 direction register. Bits set to 0 are in output
-    /// mode, while bits set to 1 are in input mode.
-    pub fn set_port_0_direction(&mut self, dir_mask: u8) -> Result<(), E> {
-        self.i2c
-            .write(self.address.addr(), &[CONFIGURATION_PORT_0, dir_mask])
-    }
-
-    /// Set the port 0 polarity inversion register. Bits set to 1 have
-    /// their polarity inverted
-    pub fn set_port_0_polarity_invert(
-        &mut self,
-        polarity_mask: u8,
-    ) -> Result<(), E> {
-        self.i2c.write(
-            self.address.addr(),
-            &[POLARITY_INVERT_PORT_0, polarity_mask],
-        )
+    /// Return the register cache and last inputs to the power-on defaults,
+    /// after the chip has been reset
+    fn reset_cache(&mut self) {
+        self.registers = Registers::POWER_ON;
+        self.inputs = 0xffff;
     }
 
-    /// Write the given byte to port 1. Has no effect on pins which have
-    /// been configured as inputs.
-    pub fn write_port_1(&mut self, value: u8) -> Result<(), E> {
-        self.i2c.write(self.address.addr(), &[WRITE_PORT_1, value])
+    /// Destroy the driver and return the I2C bus. The chip keeps its
+    /// current register contents.
+    pub fn release(self) -> I2C {
+        self.i2c
     }
 
-    /// Set the port 1 direction register. Bits set to 0 are in output
-    /// mode, while bits set to 1 are in input mode.
-    pub fn set_port_1_direction(&mut self, dir_mask: u8) -> Result<(), E> {
-        self.i2c
-            .write(self.address.addr(), &[CONFIGURATION_PORT_1, dir_mask])
-    }
-
-    /// Set the port 1 polarity inversion register. Bits set to 1 have
-    /// their polarity inverted
-    pub fn set_port_1_polarity_invert(
-        &mut self,
-        polarity_mask: u8,
-    ) -> Result<(), E> {
-        self.i2c.write(
-            self.address.addr(),
-            &[POLARITY_INVERT_PORT_1, polarity_mask],
-        )
+    /// Split the device into its 16 individual pins. The driver is shared
+    /// between the pins through the provided `RefCell`, so each pin can be
+    /// handed to a different consumer without requiring an allocator. The
+    /// `RefCell` is borrowed mutably so that it cannot be split again while
+    /// the pins exist; use [`Parts::driver`] to reach the whole driver.
+    ///
+    /// ```no_run
+    /// # use embedded_hal::digital::OutputPin;
+    /// # use embedded_hal::i2c::I2c;
+    /// use core::cell::RefCell;
+    /// use tca9555::{DeviceAddr, Error, Tca9555};
+    /// # fn example<I2C: I2c>(i2c: I2C) -> Result<(), Error<I2C::Error>> {
+    /// let mut tca = RefCell::new(Tca9555::new(i2c, DeviceAddr::default()));
+    /// let mut pins = Tca9555::split(&mut tca);
+    /// pins.p00.set_as_output()?;
+    /// pins.p00.set_high()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Splitting the same driver twice does not compile:
+    ///
+    /// ```compile_fail
+    /// # use core::cell::RefCell;
+    /// # use embedded_hal::i2c::I2c;
+    /// # use tca9555::{DeviceAddr, Tca9555};
+    /// # fn example<I2C: I2c>(i2c: I2C) {
+    /// let mut tca = RefCell::new(Tca9555::new(i2c, DeviceAddr::default()));
+    /// let pins = Tca9555::split(&mut tca);
+    /// let again = Tca9555::split(&mut tca);
+    /// # drop((pins, again));
+    /// # }
+    /// ```
+    pub fn split(driver: &mut RefCell<Self>) -> Parts<'_, I2C, C>
+    where
+        C: TwoPorts,
+    {
+        Parts::new(driver)
     }
 
-    /// Write the given u16 across all 16 output pins
-    pub fn write_all(&mut self, value: u16) -> Result<(), E> {
-        let [port1, port0] = value.to_be_bytes();
-        self.write_port_0(port0)?;
-        self.write_port_1(port1)
+    /// Split a single-port chip into its 8 individual pins, as
+    /// [`split`](Self::split) does for the 16-bit chips
+    pub fn split8(driver: &mut RefCell<Self>) -> Parts8<'_, I2C, C>
+    where
+        C: OnePort,
+    {
+        Parts8::new(driver)
     }
 }
 
+driver_methods!(Tca9555, blocking);
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -193,4 +469,209 @@ mod test {
         assert_eq!(DeviceAddr::Alternative(true, false, false).addr(), 0x21);
         assert_eq!(DeviceAddr::Alternative(false, true, true).addr(), 0x26);
     }
+
+    #[test]
+    fn address_conversions() {
+        let a0_a2 = DeviceAddr::new(true, false, true);
+        assert_eq!(DeviceAddr::Default, DeviceAddr::new(false, false, false));
+        assert_eq!(DeviceAddr::try_from(0x25), Ok(a0_a2));
+        assert_eq!(
+            DeviceAddr::try_from(0x28),
+            Err(AddrError::OutOfRange(0x28))
+        );
+        assert_eq!(
+            DeviceAddr::try_from(0x1f),
+            Err(AddrError::OutOfRange(0x1f))
+        );
+        assert_eq!(DeviceAddr::from_pins(0b101), Some(a0_a2));
+        assert_eq!(DeviceAddr::from_pins(0b1000), None);
+        assert_eq!(a0_a2.pins(), 0b101);
+        assert_eq!(
+            DeviceAddr::all().map(DeviceAddr::addr).collect::<Vec<_>>(),
+            (0x20..=0x27).collect::<Vec<_>>()
+        );
+        let set: std::collections::HashSet<_> =
+            [DeviceAddr::Default, DeviceAddr::new(false, false, false)].into();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn address_strings() {
+        let a0_a1 = DeviceAddr::new(true, true, false);
+        assert_eq!(a0_a1.to_string(), "A0=1,A1=1,A2=0");
+        assert_eq!(a0_a1.to_string().parse(), Ok(a0_a1));
+        assert_eq!("0x23".parse(), Ok(a0_a1));
+        assert_eq!(" 0X23 ".parse(), Ok(a0_a1));
+        assert_eq!("A0=1,A1=1".parse(), Ok(a0_a1));
+        assert_eq!("a1 = 1, a0 = 1".parse(), Ok(a0_a1));
+        assert_eq!("A2=0".parse(), Ok(DeviceAddr::Default));
+        assert_eq!(
+            "0x74".parse::<DeviceAddr>(),
+            Err(AddrError::OutOfRange(0x74))
+        );
+        for invalid in [
+            "",
+            "0x",
+            "0x+23",
+            "0x123",
+            "35",
+            "A3=1",
+            "A0=2",
+            "A0=1,A0=0",
+        ] {
+            assert_eq!(invalid.parse::<DeviceAddr>(), Err(AddrError::Syntax));
+        }
+    }
+
+    #[test]
+    fn chip_addresses() {
+        use chip::{Pca9554, Tca9539, Tca9555};
+        let a1 = DeviceAddr::new(false, true, false);
+        assert_eq!(DeviceAddr::from_address::<Tca9539>(0x76), Ok(a1));
+        assert_eq!(DeviceAddr::from_address::<Pca9554>(0x22), Ok(a1));
+        assert_eq!(DeviceAddr::from_address::<Tca9555>(0x22), Ok(a1));
+        assert_eq!(
+            DeviceAddr::from_address::<Tca9539>(0x78),
+            Err(AddrError::OutOfRange(0x78))
+        );
+        assert_eq!(
+            DeviceAddr::from_address::<Tca9539>(0x22),
+            Err(AddrError::OutOfRange(0x22))
+        );
+    }
+
+    #[test]
+    fn modify_outputs_uses_cache() {
+        use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+        let expectations = [
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0x0f]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_1, 0x00]),
+            Transaction::write(0x20, vec![WRITE_PORT_1, 0x7f]),
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0x3f, 0x7e]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        tca.write_port_0(0x0f).unwrap();
+        tca.set_port_1_direction(0x00).unwrap();
+        tca.set_pin_low(15).unwrap();
+        tca.modify_outputs(0x01f0, 0x0030).unwrap();
+        assert_eq!(tca.registers().output, 0x7e3f);
+        i2c.done();
+    }
+
+    #[test]
+    fn refresh_registers_reads_back() {
+        use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+        let expectations = [
+            Transaction::write_read(0x20, vec![WRITE_PORT_0], vec![0x12, 0x34]),
+            Transaction::write_read(
+                0x20,
+                vec![POLARITY_INVERT_PORT_0],
+                vec![0x01, 0x80],
+            ),
+            Transaction::write_read(
+                0x20,
+                vec![CONFIGURATION_PORT_0],
+                vec![0xf0, 0x0f],
+            ),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        let registers = tca.refresh_registers().unwrap();
+        assert_eq!(
+            registers,
+            Registers {
+                output: 0x3412,
+                polarity_invert: 0x8001,
+                direction: 0x0ff0,
+            }
+        );
+        assert_eq!(tca.registers(), registers);
+        i2c.done();
+    }
+
+    #[test]
+    fn sixteen_bit_single_transaction() {
+        use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+        let expectations = [
+            Transaction::write_read(0x20, vec![READ_PORT_0], vec![0xcd, 0xab]),
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0x34, 0x12]),
+            Transaction::write(0x20, vec![POLARITY_INVERT_PORT_0, 0xff, 0x00]),
+            Transaction::write(0x20, vec![CONFIGURATION_PORT_0, 0x00, 0xff]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        assert_eq!(tca.read_all().unwrap(), 0xabcd);
+        tca.write_all(0x1234).unwrap();
+        tca.set_polarity_invert_all(0x00ff).unwrap();
+        tca.set_direction_all(0xff00).unwrap();
+        assert_eq!(
+            tca.registers(),
+            Registers {
+                output: 0x1234,
+                polarity_invert: 0x00ff,
+                direction: 0xff00,
+            }
+        );
+        i2c.done();
+    }
+
+    #[test]
+    fn pin_errors() {
+        use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+        let expectations = [Transaction::write_read(
+            0x20,
+            vec![WRITE_PORT_0],
+            vec![0xff, 0xfe],
+        )];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca = Tca9555::new(i2c.clone(), DeviceAddr::default());
+        assert_eq!(tca.set_pin_high(16), Err(Error::InvalidPin(16)));
+        assert_eq!(tca.set_pin_as_input(200), Err(Error::InvalidPin(200)));
+        assert_eq!(tca.set_pin_low(3), Err(Error::PinIsInput(3)));
+        assert_eq!(
+            tca.verify_registers(),
+            Err(Error::VerifyFailed {
+                register: WRITE_PORT_0,
+                expected: 0xffff,
+                actual: 0xfeff,
+            })
+        );
+        i2c.done();
+    }
+
+    #[test]
+    fn shared_bus_devices() {
+        use critical_section::Mutex;
+        use embedded_hal_bus::i2c::{
+            AtomicDevice, CriticalSectionDevice, RefCellDevice,
+        };
+        use embedded_hal_bus::util::AtomicCell;
+        use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+        let expectations = [
+            Transaction::write(0x20, vec![WRITE_PORT_0, 0x01]),
+            Transaction::write(0x48, vec![0x00]),
+            Transaction::write(0x21, vec![WRITE_PORT_1, 0x02]),
+            Transaction::write(0x22, vec![WRITE_PORT_0, 0x03]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+
+        let bus = RefCell::new(i2c.clone());
+        let mut tca =
+            Tca9555::new(RefCellDevice::new(&bus), DeviceAddr::Default);
+        tca.write_port_0(0x01).unwrap();
+        let mut device = tca.release();
+        device.write(0x48, &[0x00]).unwrap();
+
+        let bus = Mutex::new(RefCell::new(bus.into_inner()));
+        let address = DeviceAddr::Alternative(true, false, false);
+        let mut tca = Tca9555::new(CriticalSectionDevice::new(&bus), address);
+        tca.write_port_1(0x02).unwrap();
+
+        let bus = AtomicCell::new(bus.into_inner().into_inner());
+        let address = DeviceAddr::Alternative(false, true, false);
+        let mut tca = Tca9555::new(AtomicDevice::new(&bus), address);
+        tca.write_port_0(0x03).unwrap();
+        i2c.done();
+    }
 }
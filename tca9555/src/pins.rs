@@ -0,0 +1,316 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Individual pin handles implementing the embedded-hal digital traits.
+//!
+//! The pins borrow the driver through a `RefCell`, so they can be passed
+//! to other drivers in place of MCU GPIOs while all sharing the single
+//! I2C bus. Splitting takes the `RefCell` by mutable reference, so each
+//! pin handle exists only once for as long as the pins are borrowed.
+//! Output and direction changes are made against the driver's register
+//! cache, so setting one pin never disturbs its neighbours and
+//! `is_set_high` does not need to touch the bus.
+
+use crate::chip::{self, Chip};
+use crate::{Error, Tca9555};
+use core::cell::RefCell;
+use embedded_hal::digital::{
+    ErrorType, InputPin, OutputPin, StatefulOutputPin,
+};
+use embedded_hal::i2c::I2c;
+
+/// A single I/O pin of a TCA9555
+pub struct Pin<'a, I2C, C = chip::Tca9555> {
+    driver: &'a RefCell<Tca9555<I2C, C>>,
+    pin: u8,
+}
+
+impl<'a, I2C, C> Pin<'a, I2C, C> {
+    /// The pin index, where pins 0-7 are port 0 and 8-15 are port 1
+    pub fn index(&self) -> u8 {
+        self.pin
+    }
+}
+
+impl<'a, I2C: I2c, C: Chip> Pin<'a, I2C, C> {
+    /// Configure this pin as an output. The pin will drive whatever level
+    /// is currently held in the output register. The [`OutputPin`] methods
+    /// return [`Error::PinIsInput`] until this has been called, so use
+    /// [`Tca9555::modify_outputs`] to preload the level if it matters.
+    pub fn set_as_output(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.driver.borrow_mut().set_pin_as_output(self.pin)
+    }
+
+    /// Configure this pin as an input
+    pub fn set_as_input(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.driver.borrow_mut().set_pin_as_input(self.pin)
+    }
+
+    fn output_is_high(&self) -> bool {
+        self.driver.borrow().registers().output & (1 << self.pin) != 0
+    }
+
+    fn input_is_high(&self) -> Result<bool, Error<I2C::Error>> {
+        let port = self.driver.borrow_mut().read_port(self.pin / 8)?;
+        Ok(port & (1 << (self.pin % 8)) != 0)
+    }
+}
+
+impl<'a, I2C: I2c, C: Chip> ErrorType for Pin<'a, I2C, C> {
+    type Error = Error<I2C::Error>;
+}
+
+impl<'a, I2C: I2c, C: Chip> OutputPin for Pin<'a, I2C, C> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.driver.borrow_mut().set_pin_low(self.pin)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.driver.borrow_mut().set_pin_high(self.pin)
+    }
+}
+
+impl<'a, I2C: I2c, C: Chip> StatefulOutputPin for Pin<'a, I2C, C> {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.output_is_high())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_set_high().map(|high| !high)
+    }
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        self.driver.borrow_mut().toggle_pin(self.pin)
+    }
+}
+
+impl<'a, I2C: I2c, C: Chip> InputPin for Pin<'a, I2C, C> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.input_is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+#[cfg(feature = "eh02")]
+mod eh02 {
+    use super::*;
+    use embedded_hal_02::digital::v2 as hal02;
+
+    impl<'a, I2C: I2c, C: Chip> hal02::OutputPin for Pin<'a, I2C, C> {
+        type Error = Error<I2C::Error>;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            OutputPin::set_low(self)
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            OutputPin::set_high(self)
+        }
+    }
+
+    impl<'a, I2C: I2c, C: Chip> hal02::StatefulOutputPin for Pin<'a, I2C, C> {
+        fn is_set_high(&self) -> Result<bool, Self::Error> {
+            Ok(self.output_is_high())
+        }
+
+        fn is_set_low(&self) -> Result<bool, Self::Error> {
+            hal02::StatefulOutputPin::is_set_high(self).map(|high| !high)
+        }
+    }
+
+    impl<'a, I2C: I2c, C: Chip> hal02::ToggleableOutputPin for Pin<'a, I2C, C> {
+        type Error = Error<I2C::Error>;
+
+        fn toggle(&mut self) -> Result<(), Self::Error> {
+            StatefulOutputPin::toggle(self)
+        }
+    }
+
+    impl<'a, I2C: I2c, C: Chip> hal02::InputPin for Pin<'a, I2C, C> {
+        type Error = Error<I2C::Error>;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            self.input_is_high()
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            hal02::InputPin::is_high(self).map(|high| !high)
+        }
+    }
+}
+
+/// The 16 pins of a TCA9555, as returned by [`Tca9555::split`]. Pins are
+/// named after the datasheet, so `p13` is bit 3 of port 1.
+#[allow(missing_docs)]
+pub struct Parts<'a, I2C, C = chip::Tca9555> {
+    pub p00: Pin<'a, I2C, C>,
+    pub p01: Pin<'a, I2C, C>,
+    pub p02: Pin<'a, I2C, C>,
+    pub p03: Pin<'a, I2C, C>,
+    pub p04: Pin<'a, I2C, C>,
+    pub p05: Pin<'a, I2C, C>,
+    pub p06: Pin<'a, I2C, C>,
+    pub p07: Pin<'a, I2C, C>,
+    pub p10: Pin<'a, I2C, C>,
+    pub p11: Pin<'a, I2C, C>,
+    pub p12: Pin<'a, I2C, C>,
+    pub p13: Pin<'a, I2C, C>,
+    pub p14: Pin<'a, I2C, C>,
+    pub p15: Pin<'a, I2C, C>,
+    pub p16: Pin<'a, I2C, C>,
+    pub p17: Pin<'a, I2C, C>,
+    driver: &'a RefCell<Tca9555<I2C, C>>,
+}
+
+impl<'a, I2C, C> Parts<'a, I2C, C> {
+    pub(crate) fn new(driver: &'a RefCell<Tca9555<I2C, C>>) -> Self {
+        let pin = |pin| Pin { driver, pin };
+        Self {
+            p00: pin(0),
+            p01: pin(1),
+            p02: pin(2),
+            p03: pin(3),
+            p04: pin(4),
+            p05: pin(5),
+            p06: pin(6),
+            p07: pin(7),
+            p10: pin(8),
+            p11: pin(9),
+            p12: pin(10),
+            p13: pin(11),
+            p14: pin(12),
+            p15: pin(13),
+            p16: pin(14),
+            p17: pin(15),
+            driver,
+        }
+    }
+
+    /// Get the driver shared by the pins, for operations on whole ports
+    pub fn driver(&self) -> &'a RefCell<Tca9555<I2C, C>> {
+        self.driver
+    }
+}
+
+/// The 8 pins of a single-port chip such as the PCA9554, as returned by
+/// [`Tca9555::split8`]
+#[allow(missing_docs)]
+pub struct Parts8<'a, I2C, C> {
+    pub p0: Pin<'a, I2C, C>,
+    pub p1: Pin<'a, I2C, C>,
+    pub p2: Pin<'a, I2C, C>,
+    pub p3: Pin<'a, I2C, C>,
+    pub p4: Pin<'a, I2C, C>,
+    pub p5: Pin<'a, I2C, C>,
+    pub p6: Pin<'a, I2C, C>,
+    pub p7: Pin<'a, I2C, C>,
+    driver: &'a RefCell<Tca9555<I2C, C>>,
+}
+
+impl<'a, I2C, C> Parts8<'a, I2C, C> {
+    pub(crate) fn new(driver: &'a RefCell<Tca9555<I2C, C>>) -> Self {
+        let pin = |pin| Pin { driver, pin };
+        Self {
+            p0: pin(0),
+            p1: pin(1),
+            p2: pin(2),
+            p3: pin(3),
+            p4: pin(4),
+            p5: pin(5),
+            p6: pin(6),
+            p7: pin(7),
+            driver,
+        }
+    }
+
+    /// Get the driver shared by the pins, for operations on whole ports
+    pub fn driver(&self) -> &'a RefCell<Tca9555<I2C, C>> {
+        self.driver
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::*;
+    use crate::DeviceAddr;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    const ADDR: u8 = 0x20;
+
+    #[test]
+    fn set_low_preserves_other_pins() {
+        let expectations = [
+            Transaction::write(ADDR, vec![CONFIGURATION_PORT_1, 0xf7]),
+            Transaction::write(ADDR, vec![CONFIGURATION_PORT_1, 0xf5]),
+            Transaction::write(ADDR, vec![WRITE_PORT_1, 0xf7]),
+            Transaction::write(ADDR, vec![WRITE_PORT_1, 0xf5]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca =
+            RefCell::new(Tca9555::new(i2c.clone(), DeviceAddr::default()));
+        let mut pins = Tca9555::split(&mut tca);
+        pins.p13.set_as_output().unwrap();
+        pins.p11.set_as_output().unwrap();
+        pins.p13.set_low().unwrap();
+        pins.p11.set_low().unwrap();
+        assert!(pins.p13.is_set_low().unwrap());
+        assert!(pins.p12.is_set_high().unwrap());
+        i2c.done();
+    }
+
+    #[test]
+    fn set_as_output_clears_config_bit() {
+        let expectations =
+            [Transaction::write(ADDR, vec![CONFIGURATION_PORT_0, 0xdf])];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca =
+            RefCell::new(Tca9555::new(i2c.clone(), DeviceAddr::default()));
+        let mut pins = Tca9555::split(&mut tca);
+        pins.p05.set_as_output().unwrap();
+        i2c.done();
+    }
+
+    #[test]
+    fn driver_shared_with_pins() {
+        let expectations = [
+            Transaction::write(ADDR, vec![CONFIGURATION_PORT_0, 0xfe]),
+            Transaction::write(ADDR, vec![WRITE_PORT_0, 0xfe]),
+            Transaction::write_read(ADDR, vec![READ_PORT_0], vec![0x00, 0x80]),
+            Transaction::write_read(ADDR, vec![READ_PORT_1], vec![0x80]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca =
+            RefCell::new(Tca9555::new(i2c.clone(), DeviceAddr::default()));
+        let mut pins = Tca9555::split(&mut tca);
+        pins.p00.set_as_output().unwrap();
+        pins.p00.set_low().unwrap();
+        assert_eq!(pins.driver().borrow_mut().read_all(), Ok(0x8000));
+        assert!(pins.p17.is_high().unwrap());
+        i2c.done();
+    }
+
+    #[test]
+    fn input_and_toggle() {
+        let expectations = [
+            Transaction::write_read(ADDR, vec![READ_PORT_1], vec![0x80]),
+            Transaction::write(ADDR, vec![CONFIGURATION_PORT_0, 0xfe]),
+            Transaction::write(ADDR, vec![WRITE_PORT_0, 0xfe]),
+            Transaction::write(ADDR, vec![WRITE_PORT_0, 0xff]),
+        ];
+        let mut i2c = Mock::new(&expectations);
+        let mut tca =
+            RefCell::new(Tca9555::new(i2c.clone(), DeviceAddr::default()));
+        let mut pins = Tca9555::split(&mut tca);
+        assert!(pins.p17.is_high().unwrap());
+        assert_eq!(pins.p00.toggle(), Err(Error::PinIsInput(0)));
+        pins.p00.set_as_output().unwrap();
+        pins.p00.toggle().unwrap();
+        pins.p00.toggle().unwrap();
+        i2c.done();
+    }
+}
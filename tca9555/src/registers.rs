@@ -0,0 +1,152 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Register cache shared between the blocking and async drivers
+
+use crate::command::*;
+use crate::Error;
+
+/// Cached copy of the writable registers of a TCA9555. Bit `n` of each
+/// field corresponds to pin `n`, where pins 0-7 are port 0 and pins 8-15
+/// are port 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct Registers {
+    /// Output port registers
+    pub output: u16,
+    /// Polarity inversion registers. Bits set to 1 have their polarity
+    /// inverted
+    pub polarity_invert: u16,
+    /// Configuration registers. Bits set to 0 are in output mode, while
+    /// bits set to 1 are in input mode.
+    pub direction: u16,
+}
+
+impl Registers {
+    /// Register contents after power-on: all pins are non-inverted inputs,
+    /// with the output register set high
+    pub const POWER_ON: Self = Self {
+        output: 0xffff,
+        polarity_invert: 0x0000,
+        direction: 0xffff,
+    };
+
+    fn pair_mut(&mut self, register: u8) -> &mut u16 {
+        match register & !1 {
+            WRITE_PORT_0 => &mut self.output,
+            POLARITY_INVERT_PORT_0 => &mut self.polarity_invert,
+            CONFIGURATION_PORT_0 => &mut self.direction,
+            _ => unreachable!("register {:#04x} is not cached", register),
+        }
+    }
+
+    /// Get the mask for a single pin of a chip with `pins` pins, checking
+    /// that it is configured as an output
+    pub(crate) fn output_mask<E>(
+        &self,
+        pin: u8,
+        pins: u8,
+    ) -> Result<u16, Error<E>> {
+        let mask = pin_mask(pin, pins)?;
+        if self.direction & mask != 0 {
+            return Err(Error::PinIsInput(pin));
+        }
+        Ok(mask)
+    }
+
+    /// Update the cache to reflect a write which has been sent to the chip
+    pub(crate) fn apply(&mut self, write: &RegisterWrite) {
+        let register = write.bytes[0];
+        let cached = self.pair_mut(register);
+        let mut value = cached.to_le_bytes();
+        let first = usize::from(register & 1);
+        for (offset, byte) in write.bytes[1..write.len].iter().enumerate() {
+            value[(first + offset) % 2] = *byte;
+        }
+        *cached = u16::from_le_bytes(value);
+    }
+
+    /// Return the bits outside `mask`, which belong to pins the chip does
+    /// not have, to their power-on values
+    pub(crate) fn restrict(&mut self, mask: u16) {
+        let keep =
+            |value: u16, power_on: u16| (value & mask) | (power_on & !mask);
+        self.output = keep(self.output, Self::POWER_ON.output);
+        self.polarity_invert =
+            keep(self.polarity_invert, Self::POWER_ON.polarity_invert);
+        self.direction = keep(self.direction, Self::POWER_ON.direction);
+    }
+
+    /// Build the write which updates the bits selected by `mask` in a
+    /// register pair, touching only the ports which are affected.
+    /// `register` must be the port 0 register of the pair.
+    pub(crate) fn modify(
+        &mut self,
+        register: u8,
+        mask: u16,
+        value: u16,
+    ) -> RegisterWrite {
+        let cached = *self.pair_mut(register);
+        let value = (cached & !mask) | (value & mask);
+        let [port0, port1] = value.to_le_bytes();
+        match mask.to_le_bytes() {
+            [_, 0] => RegisterWrite::single(register, port0),
+            [0, _] => RegisterWrite::single(register + 1, port1),
+            _ => RegisterWrite::pair(register, value),
+        }
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::POWER_ON
+    }
+}
+
+/// The bytes of a single I2C write to one register or a register pair
+#[derive(Copy, Clone)]
+pub(crate) struct RegisterWrite {
+    bytes: [u8; 3],
+    len: usize,
+}
+
+impl RegisterWrite {
+    /// Write a single register
+    pub(crate) fn single(register: u8, value: u8) -> Self {
+        Self {
+            bytes: [register, value, 0],
+            len: 2,
+        }
+    }
+
+    /// Write both registers of a pair in a single transaction. The chip
+    /// toggles between the two registers of a pair on consecutive bytes.
+    pub(crate) fn pair(register: u8, value: u16) -> Self {
+        let [port0, port1] = value.to_le_bytes();
+        Self {
+            bytes: [register, port0, port1],
+            len: 3,
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Replace one byte of a register pair value
+pub(crate) fn replace_port(value: u16, port: u8, byte: u8) -> u16 {
+    let mut bytes = value.to_le_bytes();
+    bytes[usize::from(port)] = byte;
+    u16::from_le_bytes(bytes)
+}
+
+/// Get the mask for a single pin of a chip with `pins` pins
+pub(crate) fn pin_mask<E>(pin: u8, pins: u8) -> Result<u16, Error<E>> {
+    if pin < pins {
+        Ok(1 << pin)
+    } else {
+        Err(Error::InvalidPin(pin))
+    }
+}
@@ -0,0 +1,173 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Hardware reset through the active-low RESET input.
+//!
+//! Chips with a RESET input, such as the TCA9539 and PCAL6416A, can be
+//! recovered without a power cycle if they stop responding on the bus.
+//! [`ResetPin`] owns the MCU output driving RESET and a delay provider,
+//! and `hard_reset` pulses it and returns the driver's register cache to
+//! the power-on defaults. It is only available for chips implementing
+//! [`HasReset`].
+//!
+//! ```no_run
+//! use embedded_hal::delay::DelayNs;
+//! use embedded_hal::digital::OutputPin;
+//! use embedded_hal::i2c::I2c;
+//! use tca9555::reset::ResetPin;
+//! use tca9555::{DeviceAddr, Error, Tca9539};
+//! fn recover<I2C: I2c>(
+//!     i2c: I2C,
+//!     reset: impl OutputPin,
+//!     delay: impl DelayNs,
+//! ) -> Result<(), Error<I2C::Error>> {
+//!     let mut tca = Tca9539::for_chip(i2c, DeviceAddr::default())?;
+//!     let mut reset = ResetPin::new(reset, delay);
+//!     if tca.read_all().is_err() {
+//!         tca.hard_reset(&mut reset)?;
+//!         tca.apply_safe_state()?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::chip::HasReset;
+use crate::{Error, Tca9555};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::I2c;
+
+/// Time RESET is held low, with a wide margin over the minimum pulse width
+/// in the TCA9539 and PCAL6416A datasheets
+pub const RESET_PULSE_NS: u32 = 1_000;
+
+/// Time allowed after RESET is released before the chip is accessed,
+/// covering the reset time and recovery time in the datasheets
+pub const RESET_RECOVERY_NS: u32 = 1_000;
+
+/// The MCU output connected to RESET, with a delay provider for timing
+/// the reset pulse
+pub struct ResetPin<P, D> {
+    pin: P,
+    delay: D,
+}
+
+impl<P, D> ResetPin<P, D> {
+    /// Take ownership of the RESET output, which should already be driven
+    /// high, and the delay provider
+    pub fn new(pin: P, delay: D) -> Self {
+        Self { pin, delay }
+    }
+
+    /// Return the RESET output and delay provider
+    pub fn release(self) -> (P, D) {
+        (self.pin, self.delay)
+    }
+}
+
+impl<P: OutputPin, D> ResetPin<P, D> {
+    fn assert<E>(&mut self) -> Result<(), Error<E>> {
+        self.pin.set_low().map_err(Error::reset_pin)
+    }
+
+    fn release_reset<E>(&mut self) -> Result<(), Error<E>> {
+        self.pin.set_high().map_err(Error::reset_pin)
+    }
+}
+
+/// Define the hardware reset of a driver, in the same way as the register
+/// access methods shared by both drivers
+macro_rules! reset_methods {
+    ($driver:ident, $io:ident $(, $async:tt)?) => {
+        impl<I2C, E, C: HasReset> $driver<I2C, C>
+        where
+            I2C: I2c<Error = E>,
+        {
+            /// Pulse RESET low, wait for the chip to come out of reset, and
+            /// return the register cache to the power-on defaults. The safe
+            /// state is not applied; use
+            /// [`apply_safe_state`](Self::apply_safe_state) afterwards if
+            /// required.
+            pub $($async)? fn hard_reset<P: OutputPin, D: DelayNs>(
+                &mut self,
+                reset: &mut ResetPin<P, D>,
+            ) -> Result<(), Error<E>> {
+                reset.assert()?;
+                $io!(reset.delay.delay_ns(RESET_PULSE_NS));
+                reset.release_reset()?;
+                $io!(reset.delay.delay_ns(RESET_RECOVERY_NS));
+                self.reset_cache();
+                Ok(())
+            }
+        }
+    };
+}
+
+reset_methods!(Tca9555, blocking);
+
+#[cfg(feature = "async")]
+mod asynch {
+    use super::*;
+    use crate::Tca9555Async;
+    use embedded_hal_async::delay::DelayNs;
+    use embedded_hal_async::i2c::I2c;
+
+    reset_methods!(Tca9555Async, awaited, async);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::*;
+    use crate::{DeviceAddr, Registers, Tca9539};
+    use embedded_hal::digital::ErrorKind;
+    use embedded_hal_mock::eh1::delay::{self, CheckedDelay};
+    use embedded_hal_mock::eh1::digital::{self, State};
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+    use embedded_hal_mock::eh1::MockError;
+    use std::io;
+
+    #[test]
+    fn pulse_and_resync() {
+        let mut i2c =
+            Mock::new(&[Transaction::write(0x74, vec![WRITE_PORT_0, 0x00])]);
+        let mut pin = digital::Mock::new(&[
+            digital::Transaction::set(State::Low),
+            digital::Transaction::set(State::High),
+        ]);
+        let mut delay = CheckedDelay::new(&[
+            delay::Transaction::delay_ns(RESET_PULSE_NS),
+            delay::Transaction::delay_ns(RESET_RECOVERY_NS),
+        ]);
+        let mut tca =
+            Tca9539::for_chip(i2c.clone(), DeviceAddr::default()).unwrap();
+        tca.write_port_0(0x00).unwrap();
+        let mut reset = ResetPin::new(pin.clone(), delay.clone());
+        tca.hard_reset(&mut reset).unwrap();
+        assert_eq!(tca.registers(), Registers::POWER_ON);
+        i2c.done();
+        pin.done();
+        delay.done();
+    }
+
+    #[test]
+    fn pin_error() {
+        let error = MockError::Io(io::ErrorKind::Other);
+        let mut pin =
+            digital::Mock::new(&[
+                digital::Transaction::set(State::Low).with_error(error)
+            ]);
+        let mut tca =
+            Tca9539::for_chip(Mock::new(&[]), DeviceAddr::default()).unwrap();
+        let mut delay = CheckedDelay::new(&[]);
+        let mut reset = ResetPin::new(pin.clone(), delay.clone());
+        assert_eq!(
+            tca.hard_reset(&mut reset),
+            Err(Error::ResetPin(ErrorKind::Other))
+        );
+        tca.release().done();
+        pin.done();
+        delay.done();
+    }
+}
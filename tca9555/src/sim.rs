@@ -0,0 +1,392 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Software model of a TCA9555 for testing on the host.
+//!
+//! [`Tca9555Sim`] models the registers of the chip, including the command
+//! pointer which toggles between the two registers of a pair, so a driver
+//! connected to [`Tca9555Sim::i2c`] sees the same bus behaviour as on real
+//! hardware. The levels on input pins can be driven from the test, and the
+//! INT output is available as an [`InputPin`] from [`Tca9555Sim::int`].
+//! Several chips at different addresses can share a bus created with
+//! [`SimI2c::new`].
+//!
+//! ```
+//! use tca9555::sim::Tca9555Sim;
+//! use tca9555::{DeviceAddr, Tca9555};
+//! let sim = Tca9555Sim::new(DeviceAddr::default());
+//! let mut tca = Tca9555::new(sim.i2c(), DeviceAddr::default());
+//! tca.set_direction_all(0xff00).unwrap();
+//! tca.write_all(0x0042).unwrap();
+//! sim.set_input(9, false);
+//! assert_eq!(sim.pin_levels(), 0xfd42);
+//! assert!(sim.int_asserted());
+//! assert_eq!(tca.read_all().unwrap(), 0xfd42);
+//! assert!(!sim.int_asserted());
+//! ```
+
+use crate::command::*;
+use crate::{DeviceAddr, Registers};
+use core::cell::RefCell;
+use core::convert::Infallible;
+use embedded_hal::digital::{self, InputPin};
+use embedded_hal::i2c::{
+    self, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation,
+};
+
+/// Simulated TCA9555 at a single address
+pub struct Tca9555Sim {
+    address: u8,
+    state: RefCell<State>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct State {
+    registers: Registers,
+    /// Levels driven onto the pins from outside the chip
+    external: u16,
+    /// Pin levels at the most recent read of each input port, which INT
+    /// compares against
+    captured: u16,
+    /// Register selected by the most recent command byte
+    pointer: u8,
+}
+
+impl State {
+    /// Power-on state, with every pin pulled up
+    const POWER_ON: Self = Self {
+        registers: Registers::POWER_ON,
+        external: 0xffff,
+        captured: 0xffff,
+        pointer: READ_PORT_0,
+    };
+
+    /// Electrical level of each pin: the output register for pins
+    /// configured as outputs, otherwise the externally driven level
+    fn levels(&self) -> u16 {
+        let direction = self.registers.direction;
+        (self.external & direction) | (self.registers.output & !direction)
+    }
+
+    fn int_asserted(&self) -> bool {
+        (self.levels() ^ self.captured) & self.registers.direction != 0
+    }
+
+    fn register_pair(&mut self, register: u8) -> &mut u16 {
+        match register & !1 {
+            WRITE_PORT_0 => &mut self.registers.output,
+            POLARITY_INVERT_PORT_0 => &mut self.registers.polarity_invert,
+            CONFIGURATION_PORT_0 => &mut self.registers.direction,
+            _ => unreachable!("register {:#04x} is read-only", register),
+        }
+    }
+
+    fn set_pointer(&mut self, command: u8) -> Result<(), SimError> {
+        if command > CONFIGURATION_PORT_1 {
+            return Err(SimError::InvalidCommand(command));
+        }
+        self.pointer = command;
+        Ok(())
+    }
+
+    /// Read the selected register, then toggle to the other register of
+    /// the pair
+    fn read(&mut self) -> u8 {
+        let port = usize::from(self.pointer & 1);
+        let value = match self.pointer {
+            READ_PORT_0 | READ_PORT_1 => {
+                let levels = self.levels();
+                let mask = 0xff << (8 * port);
+                self.captured = (self.captured & !mask) | (levels & mask);
+                levels ^ self.registers.polarity_invert
+            }
+            register => *self.register_pair(register),
+        };
+        self.pointer ^= 1;
+        value.to_le_bytes()[port]
+    }
+
+    /// Write the selected register, then toggle to the other register of
+    /// the pair. Writes to the input ports are ignored.
+    fn write(&mut self, byte: u8) {
+        let port = usize::from(self.pointer & 1);
+        if self.pointer > READ_PORT_1 {
+            let register = self.register_pair(self.pointer);
+            let mut value = register.to_le_bytes();
+            value[port] = byte;
+            *register = u16::from_le_bytes(value);
+        }
+        self.pointer ^= 1;
+    }
+}
+
+impl Tca9555Sim {
+    /// Create a simulated chip at the given address, in its power-on state
+    /// with every input pulled high
+    pub fn new(address: DeviceAddr) -> Self {
+        Self {
+            address: address.addr(),
+            state: RefCell::new(State::POWER_ON),
+        }
+    }
+
+    /// Get an I2C bus connected to the chip. Transactions addressed to any
+    /// other device are not acknowledged.
+    pub fn i2c(&self) -> SimI2c<'_> {
+        SimI2c::new(core::slice::from_ref(self))
+    }
+
+    /// Get the INT output of the chip, which is active low
+    pub fn int(&self) -> SimInt<'_> {
+        SimInt { sim: self }
+    }
+
+    /// Drive a single pin high or low from outside the chip. This only
+    /// affects the pin level while it is configured as an input.
+    ///
+    /// # Panics
+    /// If `pin` is not in the range 0-15
+    pub fn set_input(&self, pin: u8, high: bool) {
+        assert!(pin < 16, "pin {} is out of range", pin);
+        let mut state = self.state.borrow_mut();
+        if high {
+            state.external |= 1 << pin;
+        } else {
+            state.external &= !(1 << pin);
+        }
+    }
+
+    /// Drive all 16 pins from outside the chip, where bit `n` is pin `n`
+    pub fn set_inputs(&self, levels: u16) {
+        self.state.borrow_mut().external = levels;
+    }
+
+    /// Electrical level of each pin, taking into account which pins are
+    /// configured as outputs
+    pub fn pin_levels(&self) -> u16 {
+        self.state.borrow().levels()
+    }
+
+    /// Current contents of the output, polarity inversion and
+    /// configuration registers
+    pub fn registers(&self) -> Registers {
+        self.state.borrow().registers
+    }
+
+    /// Returns `true` if the INT output is being pulled low
+    pub fn int_asserted(&self) -> bool {
+        self.state.borrow().int_asserted()
+    }
+
+    /// Return the chip to its power-on state, as after a power cycle. The
+    /// externally driven levels are kept.
+    pub fn reset(&self) {
+        let mut state = self.state.borrow_mut();
+        let external = state.external;
+        *state = State::POWER_ON;
+        state.external = external;
+        state.captured = state.levels();
+    }
+
+    fn transaction(
+        &self,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SimError> {
+        let mut state = self.state.borrow_mut();
+        let mut writing = false;
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    // Consecutive writes are sent without a repeated
+                    // start, so only the first byte is a command
+                    let mut bytes = bytes.iter();
+                    if !writing {
+                        if let Some(&command) = bytes.next() {
+                            state.set_pointer(command)?;
+                            writing = true;
+                        }
+                    }
+                    for &byte in bytes {
+                        state.write(byte);
+                    }
+                }
+                Operation::Read(buffer) => {
+                    writing = false;
+                    for byte in buffer.iter_mut() {
+                        *byte = state.read();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error returned by [`SimI2c`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SimError {
+    /// The transaction was addressed to a different device
+    AddressNack,
+    /// The command byte does not select a register
+    InvalidCommand(u8),
+}
+
+impl i2c::Error for SimError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::AddressNack => {
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+            }
+            Self::InvalidCommand(_) => {
+                ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)
+            }
+        }
+    }
+}
+
+/// I2C bus connected to one or more [`Tca9555Sim`]s
+#[derive(Copy, Clone)]
+pub struct SimI2c<'a> {
+    chips: &'a [Tca9555Sim],
+}
+
+impl<'a> SimI2c<'a> {
+    /// Create a bus shared by several simulated chips. Transactions which
+    /// are not addressed to any of them are not acknowledged.
+    pub fn new(chips: &'a [Tca9555Sim]) -> Self {
+        Self { chips }
+    }
+
+    fn transaction(
+        &self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SimError> {
+        self.chips
+            .iter()
+            .find(|chip| chip.address == address)
+            .ok_or(SimError::AddressNack)?
+            .transaction(operations)
+    }
+}
+
+impl<'a> ErrorType for SimI2c<'a> {
+    type Error = SimError;
+}
+
+impl<'a> I2c for SimI2c<'a> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SimError> {
+        SimI2c::transaction(self, address, operations)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> embedded_hal_async::i2c::I2c for SimI2c<'a> {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), SimError> {
+        SimI2c::transaction(self, address, operations)
+    }
+}
+
+/// INT output of a [`Tca9555Sim`], as returned by [`Tca9555Sim::int`]
+#[derive(Copy, Clone)]
+pub struct SimInt<'a> {
+    sim: &'a Tca9555Sim,
+}
+
+impl<'a> digital::ErrorType for SimInt<'a> {
+    type Error = Infallible;
+}
+
+impl<'a> InputPin for SimInt<'a> {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.sim.int_asserted())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(self.sim.int_asserted())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Tca9555;
+
+    #[test]
+    fn power_on_defaults() {
+        let sim = Tca9555Sim::new(DeviceAddr::default());
+        let mut tca = Tca9555::new(sim.i2c(), DeviceAddr::default());
+        tca.set_direction_all(0x0000).unwrap();
+        tca.write_all(0x1234).unwrap();
+        sim.reset();
+        tca.refresh_registers().unwrap();
+        assert_eq!(tca.registers(), Registers::POWER_ON);
+        assert_eq!(tca.read_all().unwrap(), 0xffff);
+        assert!(!sim.int_asserted());
+    }
+
+    #[test]
+    fn pair_toggling() {
+        let sim = Tca9555Sim::new(DeviceAddr::default());
+        let mut i2c = sim.i2c();
+        i2c.write(0x20, &[WRITE_PORT_1, 0x12, 0x34, 0x56]).unwrap();
+        assert_eq!(sim.registers().output, 0x5634);
+        let mut buffer = [0; 3];
+        i2c.write_read(0x20, &[WRITE_PORT_0], &mut buffer).unwrap();
+        assert_eq!(buffer, [0x34, 0x56, 0x34]);
+        // The pointer is kept between transactions
+        i2c.read(0x20, &mut buffer[..1]).unwrap();
+        assert_eq!(buffer[0], 0x56);
+        assert_eq!(
+            i2c.write(0x20, &[0x08, 0x00]),
+            Err(SimError::InvalidCommand(0x08))
+        );
+        assert_eq!(i2c.write(0x21, &[0x02]), Err(SimError::AddressNack));
+    }
+
+    #[test]
+    fn polarity_and_direction() {
+        let sim = Tca9555Sim::new(DeviceAddr::default());
+        let mut tca = Tca9555::new(sim.i2c(), DeviceAddr::default());
+        // Outputs are ignored while the pins are inputs
+        tca.write_all(0x0000).unwrap();
+        assert_eq!(sim.pin_levels(), 0xffff);
+        tca.set_direction_all(0xff00).unwrap();
+        assert_eq!(sim.pin_levels(), 0xff00);
+        // External levels only apply to inputs
+        sim.set_inputs(0x0f0f);
+        assert_eq!(sim.pin_levels(), 0x0f00);
+        tca.set_polarity_invert_all(0x8001).unwrap();
+        assert_eq!(tca.read_all().unwrap(), 0x8f01);
+    }
+
+    #[test]
+    fn int_line() {
+        let sim = Tca9555Sim::new(DeviceAddr::default());
+        let mut tca = Tca9555::new(sim.i2c(), DeviceAddr::default());
+        let mut int = sim.int();
+        tca.set_port_0_direction(0x00).unwrap();
+        // Changes on outputs do not trigger an interrupt
+        tca.write_port_0(0x00).unwrap();
+        assert_eq!(tca.poll_interrupt(&mut int).unwrap(), None);
+        tca.read_all().unwrap();
+        // Returning to the captured level clears the interrupt
+        sim.set_input(12, false);
+        assert!(int.is_low().unwrap());
+        sim.set_input(12, true);
+        assert!(int.is_high().unwrap());
+        sim.set_input(12, false);
+        let changes = tca.poll_interrupt(&mut int).unwrap().unwrap();
+        assert_eq!(changes.falling, 0x1000);
+        assert!(int.is_high().unwrap());
+    }
+}